@@ -0,0 +1,63 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+use elliptician::{avg_color, Model, Optimizer};
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATED_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+fn main() {
+    let img = image::open("examples/monalisa_s.jpg").unwrap().to_rgba8();
+    let w = img.width();
+    let h = img.height();
+    let img_raw = img.into_raw();
+
+    let steps = 20;
+    let optimizer = Optimizer {
+        num: 2 * steps,
+        seed_count: 32,
+        optimize_count: 64,
+        ..Optimizer::default()
+    };
+    let bg_color = avg_color(w, h, &img_raw);
+    let mut model = Model::new(w, h, img_raw, bg_color, optimizer);
+
+    // warm up
+    model.step();
+
+    ALLOCATIONS.store(0, Ordering::Relaxed);
+    ALLOCATED_BYTES.store(0, Ordering::Relaxed);
+    let start = Instant::now();
+    for _ in 0..steps {
+        model.step();
+    }
+    let duration = start.elapsed();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let allocated_bytes = ALLOCATED_BYTES.load(Ordering::Relaxed);
+
+    println!("{}x{} image, {} steps", w, h, steps);
+    println!("time per step        : {:?}", duration / steps);
+    println!("allocations per step : {}", allocations / steps as usize);
+    println!(
+        "bytes per step       : {}",
+        allocated_bytes / steps as usize
+    );
+}
@@ -0,0 +1,321 @@
+use std::fmt;
+use std::fs;
+use std::str::FromStr;
+
+use tiny_skia::*;
+
+use crate::shape::{Primitive, ShapeType};
+use crate::{DrawCommand, Optimizer, PainterOptions};
+
+const HEADER: &str = "elliptician-checkpoint 1";
+
+// Everything needed to continue a run. The candidate generators are derived
+// from the seed and the iteration, so those two are the whole RNG state.
+//
+// Text format, one `key value` per line, floats written with round-trip
+// precision:
+//   elliptician-checkpoint 1
+//   size 256 256
+//   iteration 120
+//   bg_color 12 34 56 255
+//   num 1000
+//   ... the other optimizer options ...
+//   palette #000000 #ff0000   (only with a palette)
+//   placement uniform
+//   orient false
+//   auto_weight sobel         (only with automatic weights)
+//   strategy hill
+//   temperature 0.001
+//   cooling 0.95
+//   work_size 128             (only with a work size)
+//   pyramid 3
+//   weight_mask /mask.png     (only with a mask)
+//   commands 2
+//   ellipse 10.5 20 4 3 45 200 100 50 128
+//   triangle 1 2 3 4 5 6 0 0 0 128
+#[derive(Clone, Debug, PartialEq)]
+pub struct Checkpoint {
+    pub w: u32,
+    pub h: u32,
+    pub iteration: u32,
+    pub bg_color: ColorU8,
+    pub optimizer: Optimizer,
+    // the levels and mask of a `Painter` run, defaults for a bare `Model`
+    pub painter: PainterOptions,
+    pub commands: Vec<DrawCommand>,
+}
+
+fn next_value<'a>(lines: &mut std::str::Lines<'a>, key: &str) -> Result<&'a str, String> {
+    let line = lines.next().ok_or(format!("missing {}", key))?;
+    let mut parts = line.splitn(2, ' ');
+    if parts.next() != Some(key) {
+        return Err(format!("expected {}: {}", key, line));
+    }
+    Ok(parts.next().unwrap_or("").trim())
+}
+
+fn parse<T: FromStr>(value: &str, key: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid {}: {}", key, value))
+}
+
+fn parse_list<T: FromStr>(value: &str, key: &str) -> Result<Vec<T>, String> {
+    value.split_whitespace().map(|v| parse(v, key)).collect()
+}
+
+fn parse_field<T: FromStr>(lines: &mut std::str::Lines, key: &str) -> Result<T, String> {
+    parse(next_value(lines, key)?, key)
+}
+
+// a field only written when it is set
+fn parse_optional<T: FromStr>(lines: &mut std::str::Lines, key: &str) -> Result<Option<T>, String> {
+    let present = lines
+        .clone()
+        .next()
+        .is_some_and(|line| line.split(' ').next() == Some(key));
+    if !present {
+        return Ok(None);
+    }
+    parse_field(lines, key).map(Some)
+}
+
+fn parse_command(line: &str) -> Result<DrawCommand, String> {
+    let mut parts = line.split_whitespace();
+    let shape_type: ShapeType = parts.next().unwrap_or("").parse()?;
+    let values: Vec<&str> = parts.collect();
+    if values.len() < 4 {
+        return Err(format!("invalid command: {}", line));
+    }
+    let (params, rgba) = values.split_at(values.len() - 4);
+    let params: Vec<f32> = params
+        .iter()
+        .map(|v| parse(v, "command"))
+        .collect::<Result<_, _>>()?;
+    let rgba: Vec<u8> = rgba
+        .iter()
+        .map(|v| parse(v, "command"))
+        .collect::<Result<_, _>>()?;
+    let shape =
+        Primitive::from_params(shape_type, &params).ok_or(format!("invalid command: {}", line))?;
+    let color = ColorU8::from_rgba(rgba[0], rgba[1], rgba[2], rgba[3]);
+    Ok(DrawCommand { shape, color })
+}
+
+impl fmt::Display for Checkpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let o = &self.optimizer;
+        let c = &self.bg_color;
+        writeln!(f, "{}", HEADER)?;
+        writeln!(f, "size {} {}", self.w, self.h)?;
+        writeln!(f, "iteration {}", self.iteration)?;
+        writeln!(
+            f,
+            "bg_color {} {} {} {}",
+            c.red(),
+            c.green(),
+            c.blue(),
+            c.alpha()
+        )?;
+        writeln!(f, "num {}", o.num)?;
+        writeln!(f, "alpha_min {}", o.alpha_min)?;
+        writeln!(f, "alpha_max {}", o.alpha_max)?;
+        writeln!(f, "brush_scale {}", o.brush_scale)?;
+        writeln!(f, "seed_count {}", o.seed_count)?;
+        writeln!(f, "optimize_count {}", o.optimize_count)?;
+        writeln!(f, "mse_ratio {}", o.mse_ratio)?;
+        writeln!(f, "shape {}", o.shape)?;
+        writeln!(f, "color_mode {}", o.color_mode)?;
+        writeln!(f, "seed {}", o.seed)?;
+        if let Some(ref palette) = o.palette {
+            writeln!(f, "palette {}", palette)?;
+        }
+        writeln!(f, "placement {}", o.placement)?;
+        writeln!(f, "orient {}", o.orient)?;
+        if let Some(auto_weight) = o.auto_weight {
+            writeln!(f, "auto_weight {}", auto_weight)?;
+        }
+        writeln!(f, "strategy {}", o.strategy)?;
+        writeln!(f, "temperature {}", o.temperature)?;
+        writeln!(f, "cooling {}", o.cooling)?;
+        let p = &self.painter;
+        if let Some(work_size) = p.work_size {
+            writeln!(f, "work_size {}", work_size)?;
+        }
+        writeln!(f, "pyramid {}", p.pyramid)?;
+        if let Some(ref weight_mask) = p.weight_mask {
+            writeln!(f, "weight_mask {}", weight_mask)?;
+        }
+        writeln!(f, "commands {}", self.commands.len())?;
+        for cmd in self.commands.iter() {
+            write!(f, "{}", cmd.shape.shape_type())?;
+            for p in cmd.shape.params() {
+                write!(f, " {}", p)?;
+            }
+            let c = &cmd.color;
+            writeln!(f, " {} {} {} {}", c.red(), c.green(), c.blue(), c.alpha())?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Checkpoint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Checkpoint, String> {
+        let mut lines = s.lines();
+        if lines.next() != Some(HEADER) {
+            return Err("not a checkpoint file".to_string());
+        }
+
+        let size: Vec<u32> = parse_list(next_value(&mut lines, "size")?, "size")?;
+        let iteration = parse_field(&mut lines, "iteration")?;
+        let bg: Vec<u8> = parse_list(next_value(&mut lines, "bg_color")?, "bg_color")?;
+        if size.len() != 2 || bg.len() != 4 {
+            return Err("invalid size or bg_color".to_string());
+        }
+        let optimizer = Optimizer {
+            num: parse_field(&mut lines, "num")?,
+            alpha_min: parse_field(&mut lines, "alpha_min")?,
+            alpha_max: parse_field(&mut lines, "alpha_max")?,
+            brush_scale: parse_field(&mut lines, "brush_scale")?,
+            seed_count: parse_field(&mut lines, "seed_count")?,
+            optimize_count: parse_field(&mut lines, "optimize_count")?,
+            mse_ratio: parse_field(&mut lines, "mse_ratio")?,
+            shape: parse_field(&mut lines, "shape")?,
+            color_mode: parse_field(&mut lines, "color_mode")?,
+            seed: parse_field(&mut lines, "seed")?,
+            palette: parse_optional(&mut lines, "palette")?,
+            placement: parse_field(&mut lines, "placement")?,
+            orient: parse_field(&mut lines, "orient")?,
+            auto_weight: parse_optional(&mut lines, "auto_weight")?,
+            strategy: parse_field(&mut lines, "strategy")?,
+            temperature: parse_field(&mut lines, "temperature")?,
+            cooling: parse_field(&mut lines, "cooling")?,
+        };
+        let painter = PainterOptions {
+            work_size: parse_optional(&mut lines, "work_size")?,
+            pyramid: parse_field(&mut lines, "pyramid")?,
+            weight_mask: parse_optional(&mut lines, "weight_mask")?,
+        };
+
+        let count: usize = parse_field(&mut lines, "commands")?;
+        let mut commands = Vec::with_capacity(count);
+        for _ in 0..count {
+            let line = lines.next().ok_or("missing command")?;
+            commands.push(parse_command(line)?);
+        }
+
+        Ok(Checkpoint {
+            w: size[0],
+            h: size[1],
+            iteration,
+            bg_color: ColorU8::from_rgba(bg[0], bg[1], bg[2], bg[3]),
+            optimizer,
+            painter,
+            commands,
+        })
+    }
+}
+
+impl Checkpoint {
+    // written to a temporary file first so a crash never leaves a partial
+    // checkpoint behind
+    pub fn save(&self, path: &str) -> std::io::Result<()> {
+        let tmp_path = format!("{}.tmp", path);
+        fs::write(&tmp_path, self.to_string())?;
+        fs::rename(&tmp_path, path)
+    }
+
+    pub fn load(path: &str) -> Result<Checkpoint, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        text.parse()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color_solver::ColorMode;
+    use crate::importance::AutoWeight;
+    use crate::palette::Palette;
+    use crate::shape::{CubicBezier, Ellipse, Triangle};
+    use crate::{Placement, Strategy};
+    #[test]
+    fn checkpoint_test() {
+        let commands = vec![
+            DrawCommand {
+                shape: Primitive::Ellipse(Ellipse {
+                    x: 10.123457,
+                    y: 0.1 + 0.2,
+                    rx: 1e-7,
+                    ry: 3.0,
+                    angle: 179.99,
+                }),
+                color: ColorU8::from_rgba(200, 100, 50, 128),
+            },
+            DrawCommand {
+                shape: Primitive::Triangle(Triangle {
+                    points: [-1.5, 2.0, 3.25, 4.0, 5.0, 6.0],
+                }),
+                color: ColorU8::from_rgba(0, 0, 0, 255),
+            },
+            DrawCommand {
+                shape: Primitive::Cubic(CubicBezier {
+                    points: [1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0],
+                    width: 0.7,
+                }),
+                color: ColorU8::from_rgba(1, 2, 3, 4),
+            },
+        ];
+        let checkpoint = Checkpoint {
+            w: 37,
+            h: 29,
+            iteration: 3,
+            bg_color: ColorU8::from_rgba(12, 34, 56, 255),
+            optimizer: Optimizer {
+                brush_scale: 0.3,
+                mse_ratio: 1.0 / 3.0,
+                shape: ShapeType::Any,
+                color_mode: ColorMode::SolveAlpha,
+                seed: u64::MAX,
+                placement: Placement::Error,
+                orient: true,
+                auto_weight: Some(AutoWeight::Saliency),
+                strategy: Strategy::Anneal,
+                temperature: 0.25,
+                cooling: 1.0 / 3.0,
+                palette: Some(Palette::new(vec![[1, 2, 3], [255, 128, 0]])),
+                ..Optimizer::default()
+            },
+            painter: PainterOptions {
+                work_size: Some(20),
+                pyramid: 3,
+                weight_mask: Some("/masks/face mask.png".to_string()),
+            },
+            commands,
+        };
+
+        let text = checkpoint.to_string();
+        let parsed: Checkpoint = text.parse().unwrap();
+        assert_eq!(parsed, checkpoint);
+
+        let mut without_optional = checkpoint.clone();
+        without_optional.optimizer.palette = None;
+        without_optional.optimizer.auto_weight = None;
+        without_optional.painter.work_size = None;
+        without_optional.painter.weight_mask = None;
+        let parsed: Checkpoint = without_optional.to_string().parse().unwrap();
+        assert_eq!(parsed, without_optional);
+        assert!(text
+            .replace("pyramid 3\n", "")
+            .parse::<Checkpoint>()
+            .is_err());
+
+        assert!(text
+            .replace("ellipse", "hexagon")
+            .parse::<Checkpoint>()
+            .is_err());
+        assert!(text[..text.len() - 10].parse::<Checkpoint>().is_err());
+    }
+}
@@ -4,7 +4,14 @@ pub struct ColorConverter {
     table: Vec<Lab>,
 }
 
+impl Default for ColorConverter {
+    fn default() -> ColorConverter {
+        ColorConverter::new()
+    }
+}
+
 impl ColorConverter {
+    #[allow(clippy::redundant_field_names)]
     pub fn new() -> ColorConverter {
         let mut table = Vec::new();
         let lab0 = Lab::from_rgb(&[0, 0, 0]);
@@ -0,0 +1,211 @@
+use std::fmt;
+use std::str::FromStr;
+
+use tiny_skia::*;
+
+use crate::coverage::Coverage;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorMode {
+    // least squares color for the shape's coverage
+    Solve,
+    // least squares color and alpha
+    SolveAlpha,
+    // color of the pixel the shape was created at, then mutated
+    Sample,
+}
+
+impl ColorMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ColorMode::Solve => "solve",
+            ColorMode::SolveAlpha => "solve-alpha",
+            ColorMode::Sample => "sample",
+        }
+    }
+}
+
+impl fmt::Display for ColorMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for ColorMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ColorMode, String> {
+        match s {
+            "solve" => Ok(ColorMode::Solve),
+            "solve-alpha" => Ok(ColorMode::SolveAlpha),
+            "sample" => Ok(ColorMode::Sample),
+            _ => Err(format!("unknown color mode: {}", s)),
+        }
+    }
+}
+
+// Drawing color x with coverage c and alpha a turns canvas pixel p into
+// p + a * c * (x - p). The sums below are all the squared RGB error against
+// target t needs as a function of x and a.
+struct Moments {
+    sum_cc: f64,       // sum(c * c)
+    sum_ct: [f64; 3],  // sum(c * (t - p))
+    sum_ccp: [f64; 3], // sum(c * c * p)
+    sum_ccpp: f64,     // sum(c * c * p * p) over channels
+    sum_cpt: f64,      // sum(c * p * (t - p)) over channels
+}
+
+impl Moments {
+    fn new(target: &[u8], canvas: &Pixmap, coverage: &Coverage) -> Moments {
+        let w = canvas.width();
+        let pixels = canvas.pixels();
+
+        let mut m = Moments {
+            sum_cc: 0.0,
+            sum_ct: [0.0; 3],
+            sum_ccp: [0.0; 3],
+            sum_ccpp: 0.0,
+            sum_cpt: 0.0,
+        };
+        for span in coverage.spans() {
+            let values = coverage.values(span);
+            for (x, &c) in (span.x0..span.x1).zip(values.iter()) {
+                let index = (x + w * span.y) as usize;
+                let p = pixels[index];
+                let t = &target[4 * index..4 * index + 3];
+                let c = c as f64;
+                let cc = c * c;
+                let p = [p.red() as f64, p.green() as f64, p.blue() as f64];
+                for k in 0..3 {
+                    let d = t[k] as f64 - p[k];
+                    m.sum_ct[k] += c * d;
+                    m.sum_ccp[k] += cc * p[k];
+                    m.sum_ccpp += cc * p[k] * p[k];
+                    m.sum_cpt += c * p[k] * d;
+                }
+                m.sum_cc += cc;
+            }
+        }
+        m
+    }
+
+    // x = sum(c * (t - p + a * c * p)) / (a * sum(c * c))
+    fn color(&self, alpha: u8) -> ColorU8 {
+        let a = alpha as f64 / 255.0;
+        if self.sum_cc == 0.0 || a == 0.0 {
+            return ColorU8::from_rgba(0, 0, 0, alpha);
+        }
+        let channel = |k: usize| {
+            let v = (self.sum_ct[k] + a * self.sum_ccp[k]) / (a * self.sum_cc);
+            v.round().clamp(0.0, 255.0) as u8
+        };
+        ColorU8::from_rgba(channel(0), channel(1), channel(2), alpha)
+    }
+
+    // with y = a * x the error is linear in (y, a); eliminating y leaves
+    // a = (sum_k(sum_ccp * sum_ct) / sum_cc - sum_cpt) / (sum_ccpp - sum_k(sum_ccp^2) / sum_cc)
+    fn alpha(&self, alpha_min: u8, alpha_max: u8) -> u8 {
+        if self.sum_cc == 0.0 {
+            return alpha_max;
+        }
+        let mut num = -self.sum_cpt;
+        let mut den = self.sum_ccpp;
+        for k in 0..3 {
+            num += self.sum_ccp[k] * self.sum_ct[k] / self.sum_cc;
+            den -= self.sum_ccp[k] * self.sum_ccp[k] / self.sum_cc;
+        }
+        // flat canvas under the shape, any alpha fits equally well
+        if den <= 1e-6 * self.sum_ccpp.max(1.0) {
+            return alpha_max;
+        }
+        let a = num / den;
+        (a * 255.0)
+            .round()
+            .clamp(alpha_min as f64, alpha_max as f64) as u8
+    }
+}
+
+pub fn solve_color(target: &[u8], canvas: &Pixmap, coverage: &Coverage, alpha: u8) -> ColorU8 {
+    Moments::new(target, canvas, coverage).color(alpha)
+}
+
+pub fn solve_color_alpha(
+    target: &[u8],
+    canvas: &Pixmap,
+    coverage: &Coverage,
+    alpha_min: u8,
+    alpha_max: u8,
+) -> ColorU8 {
+    let moments = Moments::new(target, canvas, coverage);
+    let alpha = moments.alpha(alpha_min, alpha_max);
+    moments.color(alpha)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn solve_color_test() {
+        const WIDTH: u32 = 8;
+        const HEIGHT: u32 = 8;
+
+        let mut canvas = Pixmap::new(WIDTH, HEIGHT).unwrap();
+        canvas.fill(Color::from_rgba8(0, 0, 0, 255));
+
+        let mut target = Vec::new();
+        for _ in 0..(WIDTH * HEIGHT) {
+            target.extend_from_slice(&[100, 150, 200, 255]);
+        }
+
+        let mut coverage = Coverage::new();
+        for y in 0..HEIGHT {
+            coverage.push_span(y, 0, &[1.0; WIDTH as usize]);
+        }
+
+        // 0 + 0.5 * (x - 0) = t
+        let color = solve_color(&target, &canvas, &coverage, 128);
+        assert!((color.red() as i32 - 199).abs() <= 1);
+        assert!((color.green() as i32 - 255).abs() <= 1);
+        assert_eq!(color.blue(), 255);
+        assert_eq!(color.alpha(), 128);
+    }
+
+    #[test]
+    fn solve_color_alpha_test() {
+        const WIDTH: u32 = 8;
+        const HEIGHT: u32 = 8;
+
+        // canvas with two colors, target is 25% of (200, 40, 120) over it
+        let mut canvas = Pixmap::new(WIDTH, HEIGHT).unwrap();
+        let mut target = Vec::new();
+        let mut coverage = Coverage::new();
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                let p: [u8; 3] = if x < WIDTH / 2 {
+                    [0, 0, 0]
+                } else {
+                    [240, 240, 240]
+                };
+                let index = (x + WIDTH * y) as usize;
+                canvas.pixels_mut()[index] =
+                    ColorU8::from_rgba(p[0], p[1], p[2], 255).premultiply();
+                let x = [200.0, 40.0, 120.0];
+                for k in 0..3 {
+                    let v = p[k] as f32 + 0.25 * (x[k] - p[k] as f32);
+                    target.push(v.round() as u8);
+                }
+                target.push(255);
+            }
+            coverage.push_span(y, 0, &[1.0; WIDTH as usize]);
+        }
+
+        let color = solve_color_alpha(&target, &canvas, &coverage, 1, 255);
+        assert!((color.alpha() as i32 - 64).abs() <= 2);
+        assert!((color.red() as i32 - 200).abs() <= 4);
+        assert!((color.green() as i32 - 40).abs() <= 4);
+        assert!((color.blue() as i32 - 120).abs() <= 4);
+
+        let color = solve_color_alpha(&target, &canvas, &coverage, 128, 255);
+        assert_eq!(color.alpha(), 128);
+    }
+}
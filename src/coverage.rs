@@ -0,0 +1,327 @@
+use tiny_skia::*;
+
+use crate::shape::{BoundingBox, Primitive};
+
+// sub-scanlines per pixel row of the analytic rasterizer
+const SUBSAMPLES: u32 = 4;
+
+// run of covered pixels in row `y`, from `x0` to `x1` (exclusive)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Span {
+    pub y: u32,
+    pub x0: u32,
+    pub x1: u32,
+    offset: usize,
+}
+
+// per-pixel coverage (0.0 - 1.0) of a shape, as a list of spans
+#[derive(Clone, Debug, Default)]
+pub struct Coverage {
+    spans: Vec<Span>,
+    values: Vec<f32>,
+}
+
+// pixel rows and columns touched by `bbox` (x0, y0, x1, y1), with a pixel of
+// margin for antialiasing, clipped to the image
+pub fn pixel_bounds(bbox: &BoundingBox, w: u32, h: u32) -> Option<(u32, u32, u32, u32)> {
+    let x0 = (bbox.left.floor() - 1.0).max(0.0);
+    let y0 = (bbox.top.floor() - 1.0).max(0.0);
+    let x1 = (bbox.right.ceil() + 1.0).min(w as f32);
+    let y1 = (bbox.bottom.ceil() + 1.0).min(h as f32);
+    if !(x0 < x1 && y0 < y1) {
+        return None;
+    }
+    Some((x0 as u32, y0 as u32, x1 as u32, y1 as u32))
+}
+
+fn clear_region(pixmap: &mut Pixmap, rect: (u32, u32, u32, u32)) {
+    let (x0, y0, x1, y1) = rect;
+    let w = pixmap.width();
+    let pixels = pixmap.pixels_mut();
+    for y in y0..y1 {
+        let start = (x0 + w * y) as usize;
+        let end = (x1 + w * y) as usize;
+        for pixel in &mut pixels[start..end] {
+            *pixel = PremultipliedColorU8::TRANSPARENT;
+        }
+    }
+}
+
+// adds `weight` times the overlap of [left, right] with each pixel of `row`,
+// which starts at column `x0`
+fn add_interval(row: &mut [f32], x0: u32, left: f32, right: f32, weight: f32) {
+    let left = left.max(x0 as f32);
+    let right = right.min((x0 as usize + row.len()) as f32);
+    if left >= right {
+        return;
+    }
+    let first = left.floor() as usize;
+    let last = right.ceil() as usize;
+    for x in first..last {
+        let overlap = right.min((x + 1) as f32) - left.max(x as f32);
+        let value = &mut row[x - x0 as usize];
+        *value = (*value + overlap * weight).min(1.0);
+    }
+}
+
+impl Coverage {
+    pub fn new() -> Coverage {
+        Coverage {
+            spans: Vec::new(),
+            values: Vec::new(),
+        }
+    }
+
+    // rasterizes `shape` with tiny-skia into an alpha mask
+    pub fn rasterize(shape: &Primitive, w: u32, h: u32) -> Coverage {
+        let mut mask = Pixmap::new(w, h).unwrap();
+        let mut coverage = Coverage::new();
+        coverage.rasterize_into(shape, &mut mask);
+        coverage
+    }
+
+    // same as `rasterize`, reusing this coverage and `mask`, which must be
+    // transparent and is left transparent
+    pub fn rasterize_into(&mut self, shape: &Primitive, mask: &mut Pixmap) {
+        let w = mask.width();
+        let h = mask.height();
+        match shape {
+            Primitive::Ellipse(s) => self.fill_ellipse(s.x, s.y, s.rx, s.ry, s.angle, w, h),
+            Primitive::Circle(s) => self.fill_ellipse(s.x, s.y, s.r, s.r, 0.0, w, h),
+            _ => self.rasterize_mask(shape, mask),
+        }
+    }
+
+    // coverage of `shape` drawn with tiny-skia into `mask`
+    fn rasterize_mask(&mut self, shape: &Primitive, mask: &mut Pixmap) {
+        self.clear();
+        let white = ColorU8::from_rgba(0xff, 0xff, 0xff, 0xff);
+        shape.draw(mask, &white, true);
+
+        if let Some(rect) = pixel_bounds(&shape.bounding_box(), mask.width(), mask.height()) {
+            self.read_mask(mask, rect);
+            clear_region(mask, rect);
+        }
+    }
+
+    // analytic coverage of the ellipse drawn by `renderer::fill_ellipse`,
+    // sampled on sub-scanlines with exact horizontal coverage
+    #[allow(clippy::too_many_arguments)]
+    pub fn fill_ellipse(
+        &mut self,
+        cx: f32,
+        cy: f32,
+        rx: f32,
+        ry: f32,
+        angle_degree: f32,
+        w: u32,
+        h: u32,
+    ) {
+        self.clear();
+        if rx == 0.0 || ry == 0.0 {
+            return;
+        }
+
+        // (x, y) relative to the center is inside when
+        // a * x^2 + b * x * y + c * y^2 <= 1
+        let (sin, cos) = angle_degree.to_radians().sin_cos();
+        let irx2 = 1.0 / (rx * rx);
+        let iry2 = 1.0 / (ry * ry);
+        let a = cos * cos * irx2 + sin * sin * iry2;
+        let b = 2.0 * cos * sin * (irx2 - iry2);
+        let c = sin * sin * irx2 + cos * cos * iry2;
+
+        let ex = (rx * rx * cos * cos + ry * ry * sin * sin).sqrt();
+        let ey = (rx * rx * sin * sin + ry * ry * cos * cos).sqrt();
+        let bbox = BoundingBox::from_center(cx, cy, ex, ey);
+        let (x0, y0, x1, y1) = match pixel_bounds(&bbox, w, h) {
+            Some(rect) => rect,
+            None => return,
+        };
+
+        let weight = 1.0 / SUBSAMPLES as f32;
+        for y in y0..y1 {
+            let offset = self.values.len();
+            self.values.resize(offset + (x1 - x0) as usize, 0.0);
+            let row = &mut self.values[offset..];
+            for i in 0..SUBSAMPLES {
+                let dy = y as f32 + (i as f32 + 0.5) * weight - cy;
+                let qb = b * dy;
+                let qc = c * dy * dy - 1.0;
+                let disc = qb * qb - 4.0 * a * qc;
+                if disc <= 0.0 {
+                    continue;
+                }
+                let root = disc.sqrt();
+                let left = cx + (-qb - root) / (2.0 * a);
+                let right = cx + (-qb + root) / (2.0 * a);
+                add_interval(row, x0, left, right, weight);
+            }
+
+            match row.iter().position(|&v| v > 0.0) {
+                Some(first) => {
+                    let last = row.iter().rposition(|&v| v > 0.0).unwrap();
+                    self.values
+                        .copy_within(offset + first..offset + last + 1, offset);
+                    self.values.truncate(offset + last + 1 - first);
+                    self.spans.push(Span {
+                        y,
+                        x0: x0 + first as u32,
+                        x1: x0 + last as u32 + 1,
+                        offset,
+                    });
+                }
+                None => self.values.truncate(offset),
+            }
+        }
+    }
+
+    fn read_mask(&mut self, mask: &Pixmap, rect: (u32, u32, u32, u32)) {
+        let (x0, y0, x1, y1) = rect;
+        let w = mask.width();
+        let pixels = mask.pixels();
+        for y in y0..y1 {
+            let row = &pixels[(w * y) as usize..(w * (y + 1)) as usize];
+            let first = (x0..x1).find(|&x| row[x as usize].alpha() != 0);
+            if let Some(first) = first {
+                let last = (first..x1).rev().find(|&x| row[x as usize].alpha() != 0);
+                let last = last.unwrap();
+                let offset = self.values.len();
+                for x in first..=last {
+                    self.values.push(row[x as usize].alpha() as f32 / 255.0);
+                }
+                self.spans.push(Span {
+                    y,
+                    x0: first,
+                    x1: last + 1,
+                    offset,
+                });
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.spans.clear();
+        self.values.clear();
+    }
+
+    pub fn push_span(&mut self, y: u32, x0: u32, values: &[f32]) {
+        if values.is_empty() {
+            return;
+        }
+        self.spans.push(Span {
+            y,
+            x0,
+            x1: x0 + values.len() as u32,
+            offset: self.values.len(),
+        });
+        self.values.extend_from_slice(values);
+    }
+
+    pub fn spans(&self) -> &[Span] {
+        &self.spans
+    }
+
+    pub fn values(&self, span: &Span) -> &[f32] {
+        &self.values[span.offset..span.offset + (span.x1 - span.x0) as usize]
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.spans.is_empty()
+    }
+
+    // smallest (x0, y0, x1, y1) holding all spans
+    pub fn bounds(&self) -> Option<(u32, u32, u32, u32)> {
+        let first = self.spans.first()?;
+        let mut rect = (first.x0, first.y, first.x1, first.y + 1);
+        for span in self.spans.iter() {
+            rect.0 = rect.0.min(span.x0);
+            rect.1 = rect.1.min(span.y);
+            rect.2 = rect.2.max(span.x1);
+            rect.3 = rect.3.max(span.y + 1);
+        }
+        Some(rect)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shape::{Ellipse, Triangle};
+
+    fn coverage_map(coverage: &Coverage, w: u32, h: u32) -> Vec<f32> {
+        let mut map = vec![0.0; (w * h) as usize];
+        for span in coverage.spans() {
+            for (x, &v) in (span.x0..span.x1).zip(coverage.values(span).iter()) {
+                map[(x + w * span.y) as usize] = v;
+            }
+        }
+        map
+    }
+
+    #[test]
+    fn rasterize_into_test() {
+        let shapes = [
+            Primitive::Triangle(Triangle {
+                points: [1.0, 2.0, 12.5, 4.0, 6.0, 14.0],
+            }),
+            Primitive::Triangle(Triangle {
+                points: [15.0, 1.0, 3.0, 9.5, 10.0, 15.0],
+            }),
+        ];
+
+        let mut mask = Pixmap::new(16, 16).unwrap();
+        let mut coverage = Coverage::new();
+        for shape in shapes.iter() {
+            coverage.rasterize_into(shape, &mut mask);
+            let expected = Coverage::rasterize(shape, 16, 16);
+            assert_eq!(coverage.spans(), expected.spans());
+            for span in coverage.spans() {
+                assert_eq!(coverage.values(span), expected.values(span));
+            }
+            assert!(mask.pixels().iter().all(|p| p.alpha() == 0));
+        }
+    }
+
+    #[test]
+    fn fill_ellipse_test() {
+        const WIDTH: u32 = 32;
+        const HEIGHT: u32 = 32;
+        let ellipses = [
+            (8.3, 7.7, 5.2, 2.1, 33.0),
+            (3.0, 12.0, 6.0, 4.0, 110.0),
+            (10.5, 4.5, 0.8, 0.6, 45.0),
+            (16.0, 16.0, 9.5, 9.5, 0.0),
+            (1.0, 30.0, 7.0, 3.0, 150.0),
+            (20.2, 19.9, 11.3, 1.2, 78.0),
+        ];
+
+        let mut mask = Pixmap::new(WIDTH, HEIGHT).unwrap();
+        let mut coverage = Coverage::new();
+        for &(x, y, rx, ry, angle) in ellipses.iter() {
+            let shape = Primitive::Ellipse(Ellipse {
+                x,
+                y,
+                rx,
+                ry,
+                angle,
+            });
+            coverage.rasterize_into(&shape, &mut mask);
+            let actual = coverage_map(&coverage, WIDTH, HEIGHT);
+            coverage.rasterize_mask(&shape, &mut mask);
+            let expected = coverage_map(&coverage, WIDTH, HEIGHT);
+
+            let mut max_error = 0.0_f32;
+            let mut sum_error = 0.0_f32;
+            let mut sum_expected = 0.0_f32;
+            for (a, e) in actual.iter().zip(expected.iter()) {
+                max_error = max_error.max((a - e).abs());
+                sum_error += (a - e).abs();
+                sum_expected += e;
+            }
+            // tiny-skia snaps each sub-scanline to a quarter pixel
+            assert!(max_error < 0.3);
+            assert!(sum_error < 0.05 * sum_expected + 1.0);
+        }
+    }
+}
@@ -1,145 +1,129 @@
 use tiny_skia::*;
 
 use lab::Lab;
+use rand::rngs::StdRng;
+use rand::Rng;
 
+use crate::coverage::Coverage;
+use crate::renderer::blend;
 use crate::ColorConverter;
 
+pub const BLOCK_SIZE: u32 = 8;
+
 pub fn diff(
     color_converter: &ColorConverter,
     lab_img: &[Lab],
     pixmap: &mut Pixmap,
     mse_ratio: f32,
 ) -> f32 {
-    let w = pixmap.width();
-    let h = pixmap.height();
-    let img2: &[PremultipliedColorU8] = pixmap.pixels_mut();
-
-    let mut sum = 0.0_f32;
-    for y in 0..h {
-        for x in 0..w {
-            let index = (x + w * y) as usize;
-            let color2 = img2[index];
+    Scorer::new(color_converter, lab_img, pixmap, mse_ratio).score()
+}
+
+fn pixel_error(color_converter: &ColorConverter, lab1: Lab, color2: PremultipliedColorU8) -> f32 {
+    assert!(color2.is_opaque());
+    let lab2 = color_converter.get_lab(color2.red(), color2.green(), color2.blue());
+
+    let dl = (lab1.l - lab2.l) * 0.01_f32;
+    let da = (lab1.a - lab2.a) * 0.01_f32;
+    let db = (lab1.b - lab2.b) * 0.01_f32;
+    (dl * dl + da * da + db * db) / 3.0_f32
+}
+
+// sum of the l, a and b ssim of block (bx, by), `pixel(i, j)` gives the
+// compared pixel at (i, j) inside the block
+fn block_ssim<F: Fn(u32, u32) -> PremultipliedColorU8>(
+    color_converter: &ColorConverter,
+    lab_img: &[Lab],
+    w: u32,
+    bx: u32,
+    by: u32,
+    pixel: F,
+) -> f32 {
+    let inv_samples = 1.0_f32 / (BLOCK_SIZE * BLOCK_SIZE) as f32;
+    let base_offset = bx * BLOCK_SIZE + by * BLOCK_SIZE * w;
+    let mut sum_l1 = 0.0_f32;
+    let mut sum_l2 = 0.0_f32;
+    let mut sum_l11 = 0.0_f32;
+    let mut sum_l22 = 0.0_f32;
+    let mut sum_l12 = 0.0_f32;
+
+    let mut sum_a1 = 0.0_f32;
+    let mut sum_a2 = 0.0_f32;
+    let mut sum_a11 = 0.0_f32;
+    let mut sum_a22 = 0.0_f32;
+    let mut sum_a12 = 0.0_f32;
+
+    let mut sum_b1 = 0.0_f32;
+    let mut sum_b2 = 0.0_f32;
+    let mut sum_b11 = 0.0_f32;
+    let mut sum_b22 = 0.0_f32;
+    let mut sum_b12 = 0.0_f32;
+
+    for j in 0..BLOCK_SIZE {
+        for i in 0..BLOCK_SIZE {
+            let index = (base_offset + i + w * j) as usize;
+            let color2 = pixel(i, j);
             let lab1 = lab_img[index];
-            assert_eq!(color2.is_opaque(), true);
+            assert!(color2.is_opaque());
             let lab2 = color_converter.get_lab(color2.red(), color2.green(), color2.blue());
+            let l1 = lab1.l;
+            let a1 = lab1.a;
+            let b1 = lab1.b;
+            let l2 = lab2.l;
+            let a2 = lab2.a;
+            let b2 = lab2.b;
 
-            let dl = (lab1.l - lab2.l) * 0.01_f32;
-            let da = (lab1.a - lab2.a) * 0.01_f32;
-            let db = (lab1.b - lab2.b) * 0.01_f32;
-            let val = (dl * dl + da * da + db * db) / 3.0_f32;
-            sum += val;
-        }
-    }
-    let mse = sum / ((w * h) as f32);
-
-    // https://en.wikipedia.org/wiki/Structural_similarity
-
-    let block_size = 8;
-    let inv_samples = 1.0_f32 / (block_size * block_size) as f32;
-    let x_block_num = w / block_size;
-    let y_block_num = h / block_size;
-
-    if x_block_num == 0 || y_block_num == 0 {
-        return 0.0;
-    }
-
-    let mut ssims: Vec<f32> = Vec::new();
-    for by in 0..y_block_num {
-        for bx in 0..x_block_num {
-            let base_offset = bx * block_size + by * block_size * w;
-            let mut sum_l1 = 0.0_f32;
-            let mut sum_l2 = 0.0_f32;
-            let mut sum_l11 = 0.0_f32;
-            let mut sum_l22 = 0.0_f32;
-            let mut sum_l12 = 0.0_f32;
-
-            let mut sum_a1 = 0.0_f32;
-            let mut sum_a2 = 0.0_f32;
-            let mut sum_a11 = 0.0_f32;
-            let mut sum_a22 = 0.0_f32;
-            let mut sum_a12 = 0.0_f32;
-
-            let mut sum_b1 = 0.0_f32;
-            let mut sum_b2 = 0.0_f32;
-            let mut sum_b11 = 0.0_f32;
-            let mut sum_b22 = 0.0_f32;
-            let mut sum_b12 = 0.0_f32;
-
-            for j in 0..block_size {
-                for i in 0..block_size {
-                    let index = (base_offset + i + w * j) as usize;
-                    let color2 = img2[index];
-                    let lab1 = lab_img[index];
-                    assert_eq!(color2.is_opaque(), true);
-                    let lab2 = color_converter.get_lab(color2.red(), color2.green(), color2.blue());
-                    let l1 = lab1.l;
-                    let a1 = lab1.a;
-                    let b1 = lab1.b;
-                    let l2 = lab2.l;
-                    let a2 = lab2.a;
-                    let b2 = lab2.b;
-
-                    sum_l1 += l1;
-                    sum_l2 += l2;
-                    sum_l11 += l1 * l1;
-                    sum_l22 += l2 * l2;
-                    sum_l12 += l1 * l2;
-
-                    sum_a1 += a1;
-                    sum_a2 += a2;
-                    sum_a11 += a1 * a1;
-                    sum_a22 += a2 * a2;
-                    sum_a12 += a1 * a2;
-
-                    sum_b1 += b1;
-                    sum_b2 += b2;
-                    sum_b11 += b1 * b1;
-                    sum_b22 += b2 * b2;
-                    sum_b12 += b1 * b2;
-                }
-            }
-
-            {
-                let avg_l1 = sum_l1 * inv_samples;
-                let var_l1 = sum_l11 * inv_samples - (avg_l1 * avg_l1);
-                let avg_l2 = sum_l2 * inv_samples;
-                let var_l2 = sum_l22 * inv_samples - (avg_l2 * avg_l2);
-                let cov_l = sum_l12 * inv_samples - (avg_l1 * avg_l2);
-                let ssim_l = get_ssim(avg_l1, avg_l2, var_l1, var_l2, cov_l);
-                ssims.push(ssim_l);
-            }
+            sum_l1 += l1;
+            sum_l2 += l2;
+            sum_l11 += l1 * l1;
+            sum_l22 += l2 * l2;
+            sum_l12 += l1 * l2;
 
-            {
-                let avg_a1 = sum_a1 * inv_samples;
-                let var_a1 = sum_a11 * inv_samples - (avg_a1 * avg_a1);
-                let avg_a2 = sum_a2 * inv_samples;
-                let var_a2 = sum_a22 * inv_samples - (avg_a2 * avg_a2);
-                let cov_a = sum_a12 * inv_samples - (avg_a1 * avg_a2);
-                let ssim_a = get_ssim(avg_a1, avg_a2, var_a1, var_a2, cov_a);
-                ssims.push(ssim_a);
-            }
+            sum_a1 += a1;
+            sum_a2 += a2;
+            sum_a11 += a1 * a1;
+            sum_a22 += a2 * a2;
+            sum_a12 += a1 * a2;
 
-            {
-                let avg_b1 = sum_b1 * inv_samples;
-                let var_b1 = sum_b11 * inv_samples - (avg_b1 * avg_b1);
-                let avg_b2 = sum_b2 * inv_samples;
-                let var_b2 = sum_b22 * inv_samples - (avg_b2 * avg_b2);
-                let cov_b = sum_b12 * inv_samples - (avg_b1 * avg_b2);
-                let ssim_b = get_ssim(avg_b1, avg_b2, var_b1, var_b2, cov_b);
-                ssims.push(ssim_b);
-            }
+            sum_b1 += b1;
+            sum_b2 += b2;
+            sum_b11 += b1 * b1;
+            sum_b22 += b2 * b2;
+            sum_b12 += b1 * b2;
         }
     }
 
-    let sum: f32 = Iterator::sum(ssims.iter());
-    let ssim = sum / (ssims.len() as f32);
-    let dssim = (1.0_f32 - ssim) * 0.5_f32;
-    let ratio = mse_ratio.min(1.0).max(0.0);
-    let result = ratio * mse + (1.0_f32 - ratio) * dssim;
-    // println!("{} {} {} {}", result, mse, dssim, ratio);
-    result
+    let ssim_l = {
+        let avg_l1 = sum_l1 * inv_samples;
+        let var_l1 = sum_l11 * inv_samples - (avg_l1 * avg_l1);
+        let avg_l2 = sum_l2 * inv_samples;
+        let var_l2 = sum_l22 * inv_samples - (avg_l2 * avg_l2);
+        let cov_l = sum_l12 * inv_samples - (avg_l1 * avg_l2);
+        get_ssim(avg_l1, avg_l2, var_l1, var_l2, cov_l)
+    };
+
+    let ssim_a = {
+        let avg_a1 = sum_a1 * inv_samples;
+        let var_a1 = sum_a11 * inv_samples - (avg_a1 * avg_a1);
+        let avg_a2 = sum_a2 * inv_samples;
+        let var_a2 = sum_a22 * inv_samples - (avg_a2 * avg_a2);
+        let cov_a = sum_a12 * inv_samples - (avg_a1 * avg_a2);
+        get_ssim(avg_a1, avg_a2, var_a1, var_a2, cov_a)
+    };
+
+    let ssim_b = {
+        let avg_b1 = sum_b1 * inv_samples;
+        let var_b1 = sum_b11 * inv_samples - (avg_b1 * avg_b1);
+        let avg_b2 = sum_b2 * inv_samples;
+        let var_b2 = sum_b22 * inv_samples - (avg_b2 * avg_b2);
+        let cov_b = sum_b12 * inv_samples - (avg_b1 * avg_b2);
+        get_ssim(avg_b1, avg_b2, var_b1, var_b2, cov_b)
+    };
+
+    ssim_l + ssim_a + ssim_b
 }
 
+#[allow(clippy::let_and_return)]
 fn get_ssim(avg1: f32, avg2: f32, var1: f32, var2: f32, cov: f32) -> f32 {
     let c1 = 6.5025_f32; // (0.01*255.0)^2
     let c2 = 58.5225_f32; // (0.03*255)^2
@@ -149,9 +133,320 @@ fn get_ssim(avg1: f32, avg2: f32, var1: f32, var2: f32, cov: f32) -> f32 {
     ssim
 }
 
+// Keeps the per-pixel error and per-block ssim of a canvas so a change
+// inside a rectangle can be scored by revisiting only that rectangle.
+// Both are stored multiplied by their weight.
+#[derive(Clone)]
+pub struct Scorer {
+    w: u32,
+    h: u32,
+    mse_ratio: f32,
+    // per-pixel weight, and its mean over each block
+    weights: Vec<f32>,
+    block_weights: Vec<f32>,
+    weight_sum: f64,
+    block_weight_sum: f64,
+    // running sum of `weights`, empty when they are all 1
+    weight_cdf: Vec<f64>,
+    pixel_errors: Vec<f32>,
+    // sum of `pixel_errors` per row
+    row_errors: Vec<f64>,
+    block_ssims: Vec<f32>,
+    mse_sum: f64,
+    ssim_sum: f64,
+}
+
+impl Scorer {
+    pub fn new(
+        color_converter: &ColorConverter,
+        lab_img: &[Lab],
+        pixmap: &Pixmap,
+        mse_ratio: f32,
+    ) -> Scorer {
+        Scorer::new_weighted(color_converter, lab_img, pixmap, mse_ratio, None)
+    }
+
+    // with `weights` (0.0 - 1.0 per pixel), each pixel counts that much in the
+    // mse and each block by its mean weight in the ssim
+    pub fn new_weighted(
+        color_converter: &ColorConverter,
+        lab_img: &[Lab],
+        pixmap: &Pixmap,
+        mse_ratio: f32,
+        weights: Option<&[f32]>,
+    ) -> Scorer {
+        let w = pixmap.width();
+        let h = pixmap.height();
+        // an all zero mask weights nothing, so it is ignored
+        let weights = weights.filter(|weights| weights.iter().any(|&v| v > 0.0));
+        let weight_cdf: Vec<f64> = match weights {
+            Some(weights) => {
+                assert_eq!(weights.len(), (w * h) as usize);
+                let mut sum = 0.0;
+                weights
+                    .iter()
+                    .map(|&v| {
+                        sum += v as f64;
+                        sum
+                    })
+                    .collect()
+            }
+            None => Vec::new(),
+        };
+        let weights = match weights {
+            Some(weights) => weights.to_vec(),
+            None => vec![1.0; (w * h) as usize],
+        };
+
+        let x_block_num = w / BLOCK_SIZE;
+        let y_block_num = h / BLOCK_SIZE;
+        let mut block_weights = Vec::new();
+        for by in 0..y_block_num {
+            for bx in 0..x_block_num {
+                let mut sum = 0.0;
+                for j in 0..BLOCK_SIZE {
+                    for i in 0..BLOCK_SIZE {
+                        sum += weights[(bx * BLOCK_SIZE + i + w * (by * BLOCK_SIZE + j)) as usize];
+                    }
+                }
+                block_weights.push(sum / (BLOCK_SIZE * BLOCK_SIZE) as f32);
+            }
+        }
+
+        let mut scorer = Scorer {
+            w,
+            h,
+            mse_ratio,
+            weight_sum: weights.iter().map(|&v| v as f64).sum(),
+            block_weight_sum: block_weights.iter().map(|&v| v as f64).sum(),
+            weights,
+            block_weights,
+            weight_cdf,
+            pixel_errors: vec![0.0; (w * h) as usize],
+            row_errors: vec![0.0; h as usize],
+            block_ssims: vec![0.0; ((w / BLOCK_SIZE) * (h / BLOCK_SIZE)) as usize],
+            mse_sum: 0.0,
+            ssim_sum: 0.0,
+        };
+        scorer.update_region(color_converter, lab_img, pixmap, (0, 0, w, h));
+        scorer
+    }
+
+    fn x_block_num(&self) -> u32 {
+        self.w / BLOCK_SIZE
+    }
+
+    fn y_block_num(&self) -> u32 {
+        self.h / BLOCK_SIZE
+    }
+
+    // blocks overlapping the rectangle
+    fn block_range(&self, rect: (u32, u32, u32, u32)) -> (u32, u32, u32, u32) {
+        let (x0, y0, x1, y1) = rect;
+        let bx1 = x1.div_ceil(BLOCK_SIZE).min(self.x_block_num());
+        let by1 = y1.div_ceil(BLOCK_SIZE).min(self.y_block_num());
+        (x0 / BLOCK_SIZE, y0 / BLOCK_SIZE, bx1, by1)
+    }
+
+    #[allow(clippy::manual_clamp)]
+    fn combine(&self, mse_sum: f64, ssim_sum: f64) -> f32 {
+        if self.x_block_num() == 0 || self.y_block_num() == 0 {
+            return 0.0;
+        }
+
+        let mse = (mse_sum / self.weight_sum) as f32;
+
+        // https://en.wikipedia.org/wiki/Structural_similarity
+        let ssim = if self.block_weight_sum > 0.0 {
+            (ssim_sum / (3.0 * self.block_weight_sum)) as f32
+        } else {
+            1.0
+        };
+        let dssim = (1.0_f32 - ssim) * 0.5_f32;
+        let ratio = self.mse_ratio.min(1.0).max(0.0);
+        ratio * mse + (1.0_f32 - ratio) * dssim
+    }
+
+    pub fn score(&self) -> f32 {
+        self.combine(self.mse_sum, self.ssim_sum)
+    }
+
+    // score of `canvas` with `color` drawn over it through `coverage`, whose
+    // spans are in row order
+    pub fn score_coverage(
+        &self,
+        color_converter: &ColorConverter,
+        lab_img: &[Lab],
+        canvas: &Pixmap,
+        coverage: &Coverage,
+        color: &ColorU8,
+    ) -> f32 {
+        let w = self.w;
+        let img = canvas.pixels();
+        let spans = coverage.spans();
+
+        let mut mse_sum = self.mse_sum;
+        for span in spans {
+            for (x, &c) in (span.x0..span.x1).zip(coverage.values(span).iter()) {
+                let index = (x + w * span.y) as usize;
+                let val = pixel_error(color_converter, lab_img[index], blend(img[index], color, c));
+                mse_sum += (self.weights[index] * val - self.pixel_errors[index]) as f64;
+            }
+        }
+
+        let mut ssim_sum = self.ssim_sum;
+        if let Some(rect) = coverage.bounds() {
+            let mut block = [PremultipliedColorU8::TRANSPARENT; (BLOCK_SIZE * BLOCK_SIZE) as usize];
+            let (bx0, by0, bx1, by1) = self.block_range(rect);
+            for by in by0..by1 {
+                let top = by * BLOCK_SIZE;
+                let first = spans.partition_point(|span| span.y < top);
+                let last = spans.partition_point(|span| span.y < top + BLOCK_SIZE);
+                for bx in bx0..bx1 {
+                    let left = bx * BLOCK_SIZE;
+                    for j in 0..BLOCK_SIZE {
+                        let start = (left + w * (top + j)) as usize;
+                        let row = &img[start..start + BLOCK_SIZE as usize];
+                        block[(BLOCK_SIZE * j) as usize..(BLOCK_SIZE * (j + 1)) as usize]
+                            .copy_from_slice(row);
+                    }
+
+                    let mut changed = false;
+                    for span in &spans[first..last] {
+                        let values = coverage.values(span);
+                        for x in span.x0.max(left)..span.x1.min(left + BLOCK_SIZE) {
+                            let i = (x - left + BLOCK_SIZE * (span.y - top)) as usize;
+                            block[i] = blend(block[i], color, values[(x - span.x0) as usize]);
+                            changed = true;
+                        }
+                    }
+                    if !changed {
+                        continue;
+                    }
+
+                    let index = (bx + self.x_block_num() * by) as usize;
+                    let val = block_ssim(color_converter, lab_img, w, bx, by, |i, j| {
+                        block[(i + BLOCK_SIZE * j) as usize]
+                    });
+                    ssim_sum += (self.block_weights[index] * val - self.block_ssims[index]) as f64;
+                }
+            }
+        }
+
+        self.combine(mse_sum, ssim_sum)
+    }
+
+    // score of `pixmap`, which may differ from the scored canvas only
+    // inside `rect` (x0, y0, x1, y1)
+    pub fn score_region(
+        &self,
+        color_converter: &ColorConverter,
+        lab_img: &[Lab],
+        pixmap: &Pixmap,
+        rect: (u32, u32, u32, u32),
+    ) -> f32 {
+        let w = self.w;
+        let img2 = pixmap.pixels();
+        let (x0, y0, x1, y1) = rect;
+
+        let mut mse_sum = self.mse_sum;
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let index = (x + w * y) as usize;
+                let val = pixel_error(color_converter, lab_img[index], img2[index]);
+                mse_sum += (self.weights[index] * val - self.pixel_errors[index]) as f64;
+            }
+        }
+
+        let mut ssim_sum = self.ssim_sum;
+        let (bx0, by0, bx1, by1) = self.block_range(rect);
+        for by in by0..by1 {
+            for bx in bx0..bx1 {
+                let index = (bx + self.x_block_num() * by) as usize;
+                let val = block_ssim(color_converter, lab_img, w, bx, by, |i, j| {
+                    img2[(bx * BLOCK_SIZE + i + w * (by * BLOCK_SIZE + j)) as usize]
+                });
+                ssim_sum += (self.block_weights[index] * val - self.block_ssims[index]) as f64;
+            }
+        }
+
+        self.combine(mse_sum, ssim_sum)
+    }
+
+    // takes the pixels inside `rect` from `pixmap`
+    pub fn update_region(
+        &mut self,
+        color_converter: &ColorConverter,
+        lab_img: &[Lab],
+        pixmap: &Pixmap,
+        rect: (u32, u32, u32, u32),
+    ) {
+        let w = self.w;
+        let img2 = pixmap.pixels();
+        let (x0, y0, x1, y1) = rect;
+
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let index = (x + w * y) as usize;
+                self.pixel_errors[index] =
+                    self.weights[index] * pixel_error(color_converter, lab_img[index], img2[index]);
+            }
+            let row = &self.pixel_errors[(w * y) as usize..(w * (y + 1)) as usize];
+            self.row_errors[y as usize] = row.iter().map(|&v| v as f64).sum();
+        }
+
+        let (bx0, by0, bx1, by1) = self.block_range(rect);
+        for by in by0..by1 {
+            for bx in bx0..bx1 {
+                let index = (bx + self.x_block_num() * by) as usize;
+                self.block_ssims[index] = self.block_weights[index]
+                    * block_ssim(color_converter, lab_img, w, bx, by, |i, j| {
+                        img2[(bx * BLOCK_SIZE + i + w * (by * BLOCK_SIZE + j)) as usize]
+                    });
+            }
+        }
+
+        // summed from scratch so rounding errors don't pile up over many updates
+        self.mse_sum = self.row_errors.iter().sum();
+        self.ssim_sum = self.block_ssims.iter().map(|&v| v as f64).sum();
+    }
+
+    // a pixel picked with probability proportional to its weight, none
+    // without weights
+    pub fn sample_weight(&self, rng: &mut StdRng) -> Option<(u32, u32)> {
+        let total = *self.weight_cdf.last()?;
+        let target = rng.gen::<f64>() * total;
+        let index = self.weight_cdf.partition_point(|&v| v <= target);
+        let index = index.min(self.weight_cdf.len() - 1) as u32;
+        Some((index % self.w, index / self.w))
+    }
+
+    // a pixel picked with probability proportional to its (weighted) error
+    pub fn sample_error(&self, rng: &mut StdRng) -> (u32, u32) {
+        let w = self.w as usize;
+        let mut target = rng.gen::<f64>() * self.mse_sum;
+        for (y, &row_error) in self.row_errors.iter().enumerate() {
+            if target < row_error {
+                let row = &self.pixel_errors[w * y..w * (y + 1)];
+                for (x, &error) in row.iter().enumerate() {
+                    if target < error as f64 {
+                        return (x as u32, y as u32);
+                    }
+                    target -= error as f64;
+                }
+                return (self.w - 1, y as u32);
+            }
+            target -= row_error;
+        }
+        // rounding, or nothing left to fix
+        (rng.gen_range(0..self.w), rng.gen_range(0..self.h))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::renderer::fill_coverage;
     #[test]
     fn dssim_test() {
         const WIDTH: u32 = 32;
@@ -169,4 +464,129 @@ mod tests {
 
         assert_eq!(res, 0.0_f32);
     }
+
+    #[test]
+    fn score_coverage_test() {
+        const WIDTH: u32 = 37;
+        const HEIGHT: u32 = 29;
+
+        let mut img_raw = Vec::new();
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                img_raw.extend_from_slice(&[(7 * x) as u8, (9 * y) as u8, (x * y) as u8, 255]);
+            }
+        }
+
+        let color_converter = ColorConverter::new();
+        let lab_img = color_converter.lab_image(WIDTH, HEIGHT, &img_raw);
+
+        let mut pixmap = Pixmap::new(WIDTH, HEIGHT).unwrap();
+        pixmap.fill(Color::from_rgba8(90, 60, 30, 255));
+        let mut scorer = Scorer::new(&color_converter, &lab_img, &pixmap, 0.1);
+
+        let rects = [
+            (3, 5, 12, 9),
+            (20, 0, 37, 29),
+            (0, 17, 9, 29),
+            (30, 24, 31, 25),
+        ];
+        for (i, &rect) in rects.iter().enumerate() {
+            let (x0, y0, x1, y1) = rect;
+            let mut coverage = Coverage::new();
+            for y in y0..y1 {
+                let values: Vec<f32> = (x0..x1).map(|x| ((x + y) % 4 + 1) as f32 / 4.0).collect();
+                coverage.push_span(y, x0, &values);
+            }
+            let color = ColorU8::from_rgba(40 * i as u8, 200, 100, 160);
+
+            let score =
+                scorer.score_coverage(&color_converter, &lab_img, &pixmap, &coverage, &color);
+            fill_coverage(&mut pixmap, &coverage, &color);
+            let expected = diff(&color_converter, &lab_img, &mut pixmap, 0.1);
+            assert!((score - expected).abs() < 1e-6, "{} {}", score, expected);
+            let score = scorer.score_region(&color_converter, &lab_img, &pixmap, rect);
+            assert!((score - expected).abs() < 1e-6, "{} {}", score, expected);
+
+            scorer.update_region(&color_converter, &lab_img, &pixmap, rect);
+            assert!((scorer.score() - expected).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn sample_error_test() {
+        const WIDTH: u32 = 24;
+        const HEIGHT: u32 = 16;
+
+        let img_raw = [40, 80, 120, 255].repeat((WIDTH * HEIGHT) as usize);
+        let color_converter = ColorConverter::new();
+        let lab_img = color_converter.lab_image(WIDTH, HEIGHT, &img_raw);
+
+        let mut pixmap = Pixmap::new(WIDTH, HEIGHT).unwrap();
+        pixmap.fill(Color::from_rgba8(40, 80, 120, 255));
+        let mut scorer = Scorer::new(&color_converter, &lab_img, &pixmap, 0.1);
+        let mut rng = rand::SeedableRng::seed_from_u64(1);
+        let (x, y) = scorer.sample_error(&mut rng);
+        assert!(x < WIDTH && y < HEIGHT);
+
+        // only (5, 3) - (9, 7) differs from the target
+        let mut paint = Paint::default();
+        paint.set_color_rgba8(255, 255, 255, 255);
+        let rect = Rect::from_xywh(5.0, 3.0, 4.0, 4.0).unwrap();
+        pixmap.fill_rect(rect, &paint, Transform::identity(), None);
+        scorer.update_region(&color_converter, &lab_img, &pixmap, (5, 3, 9, 7));
+        for _ in 0..100 {
+            let (x, y) = scorer.sample_error(&mut rng);
+            assert!((5..9).contains(&x) && (3..7).contains(&y));
+        }
+    }
+
+    #[test]
+    fn weighted_score_test() {
+        const WIDTH: u32 = 32;
+        const HEIGHT: u32 = 16;
+
+        let mut img_raw = Vec::new();
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                img_raw.extend_from_slice(&[(8 * x) as u8, (16 * y) as u8, 60, 255]);
+            }
+        }
+        let color_converter = ColorConverter::new();
+        let lab_img = color_converter.lab_image(WIDTH, HEIGHT, &img_raw);
+        let mut pixmap = Pixmap::new(WIDTH, HEIGHT).unwrap();
+        pixmap.fill(Color::from_rgba8(90, 60, 30, 255));
+
+        let ones = vec![1.0; (WIDTH * HEIGHT) as usize];
+        let scorer = Scorer::new(&color_converter, &lab_img, &pixmap, 0.1);
+        let weighted = Scorer::new_weighted(&color_converter, &lab_img, &pixmap, 0.1, Some(&ones));
+        assert_eq!(weighted.score(), scorer.score());
+
+        // only the right half counts
+        let weights: Vec<f32> = (0..WIDTH * HEIGHT)
+            .map(|i| if i % WIDTH < WIDTH / 2 { 0.0 } else { 1.0 })
+            .collect();
+        let mut scorer =
+            Scorer::new_weighted(&color_converter, &lab_img, &pixmap, 0.1, Some(&weights));
+        let color = ColorU8::from_rgba(255, 255, 255, 255);
+        let mut left = Coverage::new();
+        let mut both = Coverage::new();
+        for y in 2..10 {
+            left.push_span(y, 1, &[1.0; 12]);
+            both.push_span(y, 5, &[1.0; 20]);
+        }
+        let score = scorer.score_coverage(&color_converter, &lab_img, &pixmap, &left, &color);
+        assert_eq!(score, scorer.score());
+
+        let score = scorer.score_coverage(&color_converter, &lab_img, &pixmap, &both, &color);
+        assert!(score != scorer.score());
+        fill_coverage(&mut pixmap, &both, &color);
+        scorer.update_region(&color_converter, &lab_img, &pixmap, (5, 2, 25, 10));
+        assert!((scorer.score() - score).abs() < 1e-6);
+
+        let mut rng = rand::SeedableRng::seed_from_u64(3);
+        for _ in 0..100 {
+            let (x, y) = scorer.sample_weight(&mut rng).unwrap();
+            assert!((WIDTH / 2..WIDTH).contains(&x) && y < HEIGHT);
+        }
+    }
 }
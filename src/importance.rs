@@ -0,0 +1,193 @@
+use std::fmt;
+use std::str::FromStr;
+
+use lab::Lab;
+
+// weight of the least important pixels, so no region is ignored entirely
+const MIN_WEIGHT: f32 = 0.2;
+
+// importance map computed from the target when no mask is given
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AutoWeight {
+    // gradient magnitude, spread over the neighbourhood
+    Sobel,
+    // local variance of the Lab channels
+    Variance,
+    // distance between a fine and a coarse blur (center-surround)
+    Saliency,
+}
+
+impl AutoWeight {
+    pub fn name(&self) -> &'static str {
+        match self {
+            AutoWeight::Sobel => "sobel",
+            AutoWeight::Variance => "variance",
+            AutoWeight::Saliency => "saliency",
+        }
+    }
+}
+
+impl fmt::Display for AutoWeight {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for AutoWeight {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<AutoWeight, String> {
+        match s {
+            "sobel" => Ok(AutoWeight::Sobel),
+            "variance" => Ok(AutoWeight::Variance),
+            "saliency" => Ok(AutoWeight::Saliency),
+            _ => Err(format!("unknown auto weight: {}", s)),
+        }
+    }
+}
+
+// mean over the (2r+1)^2 window around each pixel, clipped to the image
+pub(crate) fn box_blur(values: &[f32], w: u32, h: u32, r: u32) -> Vec<f32> {
+    let (w, h, r) = (w as usize, h as usize, r as usize);
+    let mut rows = vec![0.0; w * h];
+    for y in 0..h {
+        let row = &values[w * y..w * (y + 1)];
+        for x in 0..w {
+            let (x0, x1) = (x.saturating_sub(r), (x + r + 1).min(w));
+            rows[x + w * y] = row[x0..x1].iter().sum::<f32>() / (x1 - x0) as f32;
+        }
+    }
+    let mut result = vec![0.0; w * h];
+    for x in 0..w {
+        for y in 0..h {
+            let (y0, y1) = (y.saturating_sub(r), (y + r + 1).min(h));
+            let sum: f32 = (y0..y1).map(|yy| rows[x + w * yy]).sum();
+            result[x + w * y] = sum / (y1 - y0) as f32;
+        }
+    }
+    result
+}
+
+pub(crate) fn channels(lab_img: &[Lab]) -> [Vec<f32>; 3] {
+    [
+        lab_img.iter().map(|lab| lab.l).collect(),
+        lab_img.iter().map(|lab| lab.a).collect(),
+        lab_img.iter().map(|lab| lab.b).collect(),
+    ]
+}
+
+// horizontal and vertical Sobel derivatives, edges repeated
+pub(crate) fn sobel(values: &[f32], w: u32, h: u32) -> Vec<(f32, f32)> {
+    let (w, h) = (w as i64, h as i64);
+    let at = |x: i64, y: i64| values[(x.clamp(0, w - 1) + w * y.clamp(0, h - 1)) as usize];
+    let mut result = Vec::with_capacity((w * h) as usize);
+    for y in 0..h {
+        for x in 0..w {
+            // grouped so a constant area gives exactly zero
+            let gx = (at(x + 1, y - 1) + 2.0 * at(x + 1, y) + at(x + 1, y + 1))
+                - (at(x - 1, y - 1) + 2.0 * at(x - 1, y) + at(x - 1, y + 1));
+            let gy = (at(x - 1, y + 1) + 2.0 * at(x, y + 1) + at(x + 1, y + 1))
+                - (at(x - 1, y - 1) + 2.0 * at(x, y - 1) + at(x + 1, y - 1));
+            result.push((gx, gy));
+        }
+    }
+    result
+}
+
+// per-pixel weights of the target, from MIN_WEIGHT to 1.0
+pub fn auto_weights(kind: AutoWeight, lab_img: &[Lab], w: u32, h: u32) -> Vec<f32> {
+    assert_eq!(lab_img.len(), (w * h) as usize);
+    // features are measured over about 1/64 of the image
+    let r = (w.max(h) / 64).max(1);
+    let channels = channels(lab_img);
+    let mut map = vec![0.0_f32; (w * h) as usize];
+    match kind {
+        AutoWeight::Sobel => {
+            for channel in channels.iter() {
+                for (m, (gx, gy)) in map.iter_mut().zip(sobel(channel, w, h)) {
+                    *m += gx * gx + gy * gy;
+                }
+            }
+            for m in map.iter_mut() {
+                *m = m.sqrt();
+            }
+            map = box_blur(&map, w, h, r);
+        }
+        AutoWeight::Variance => {
+            for channel in channels.iter() {
+                let squares: Vec<f32> = channel.iter().map(|v| v * v).collect();
+                let mean = box_blur(channel, w, h, r);
+                let mean_square = box_blur(&squares, w, h, r);
+                for ((m, mean), mean_square) in map.iter_mut().zip(mean).zip(mean_square) {
+                    *m += (mean_square - mean * mean).max(0.0);
+                }
+            }
+        }
+        AutoWeight::Saliency => {
+            for channel in channels.iter() {
+                let center = box_blur(channel, w, h, r);
+                let surround = box_blur(channel, w, h, 8 * r);
+                for ((m, c), s) in map.iter_mut().zip(center).zip(surround) {
+                    *m += (c - s) * (c - s);
+                }
+            }
+            for m in map.iter_mut() {
+                *m = m.sqrt();
+            }
+        }
+    }
+
+    let max = map.iter().fold(0.0_f32, |a, &b| a.max(b));
+    if max <= 0.0 {
+        return vec![1.0; map.len()];
+    }
+    map.iter()
+        .map(|&v| MIN_WEIGHT + (1.0 - MIN_WEIGHT) * v / max)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn auto_weights_test() {
+        const WIDTH: u32 = 64;
+        const HEIGHT: u32 = 32;
+
+        // dark left half, light right half, a red square on the left
+        let mut lab_img = Vec::new();
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                let rgb = if (8..14).contains(&x) && (12..18).contains(&y) {
+                    [220, 20, 20]
+                } else if x < WIDTH / 2 {
+                    [30, 30, 30]
+                } else {
+                    [200, 200, 200]
+                };
+                lab_img.push(Lab::from_rgb(&rgb));
+            }
+        }
+
+        let at = |weights: &[f32], x: u32, y: u32| weights[(x + WIDTH * y) as usize];
+        for &kind in [
+            AutoWeight::Sobel,
+            AutoWeight::Variance,
+            AutoWeight::Saliency,
+        ]
+        .iter()
+        {
+            let weights = auto_weights(kind, &lab_img, WIDTH, HEIGHT);
+            let min = weights.iter().fold(f32::MAX, |a, &b| a.min(b));
+            let max = weights.iter().fold(0.0_f32, |a, &b| a.max(b));
+            assert!((min - MIN_WEIGHT).abs() < 1e-6, "{}", kind);
+            assert!((max - 1.0).abs() < 1e-6, "{}", kind);
+            // flat areas matter less than the square
+            assert!(at(&weights, 50, 4) < at(&weights, 11, 12), "{}", kind);
+            assert_eq!(kind.name().parse::<AutoWeight>(), Ok(kind));
+        }
+
+        let flat = vec![Lab::from_rgb(&[1, 2, 3]); 16];
+        assert_eq!(auto_weights(AutoWeight::Sobel, &flat, 4, 4), vec![1.0; 16]);
+    }
+}
@@ -0,0 +1,21 @@
+pub mod checkpoint;
+pub mod color_converter;
+pub mod color_solver;
+pub mod coverage;
+pub mod draw_command;
+pub mod dssim;
+pub mod importance;
+mod model;
+pub mod orientation;
+pub mod painter;
+pub mod palette;
+pub mod renderer;
+pub mod shape;
+pub mod svg;
+
+pub use crate::color_converter::ColorConverter;
+pub use crate::draw_command::DrawCommand;
+pub use crate::model::{
+    avg_color, render, Model, Optimizer, Placement, Refinement, Step, Strategy,
+};
+pub use crate::painter::{Painter, PainterOptions};
@@ -1,352 +1,387 @@
-use std::fs;
-use std::io::Write;
-use std::sync::Mutex;
 use std::time::Instant;
 
-use lab::Lab;
-
 use tiny_skia::*;
 
-use rayon::prelude::*;
-
-mod color_converter;
-mod draw_command;
-mod dssim;
-mod renderer;
-
-use crate::color_converter::ColorConverter;
-use crate::draw_command::DrawCommand;
-
-fn avg_color(w: u32, h: u32, img: &[u8]) -> ColorU8 {
-    let mut sum_r = 0;
-    let mut sum_g = 0;
-    let mut sum_b = 0;
-    for y in 0..h {
-        for x in 0..w {
-            let index2 = (x + w * y) as usize;
-            let index = 4 * index2;
-            let r = img[index] as u32;
-            let g = img[index + 1] as u32;
-            let b = img[index + 2] as u32;
-
-            sum_r += r;
-            sum_g += g;
-            sum_b += b;
-        }
-    }
-    let count = (w * h) as u32;
-    if count == 0 {
-        return ColorU8::from_rgba(0, 0, 0, 0xff);
-    }
-    let r = (sum_r / count) as u8;
-    let g = (sum_g / count) as u8;
-    let b = (sum_b / count) as u8;
-
-    ColorU8::from_rgba(r, g, b, 0xff)
-}
-
-fn draw_cmd(pixmap: &mut Pixmap, cmd: &DrawCommand, is_antialias: bool) {
-    renderer::fill_ellipse(
-        pixmap,
-        cmd.x as f32,
-        cmd.y as f32,
-        cmd.rx as f32,
-        cmd.ry as f32,
-        cmd.angle as f32,
-        &cmd.color,
-        is_antialias,
-    );
-}
-
-fn copy_img(src: &mut Pixmap, dst: &mut Pixmap) {
-    let src_data = src.data_mut();
-    let dst_data = dst.data_mut();
-    assert_eq!(src_data.len(), dst_data.len());
-    for i in 0..src_data.len() {
-        dst_data[i] = src_data[i];
-    }
-}
+use elliptician::checkpoint::Checkpoint;
+use elliptician::color_solver::ColorMode;
+use elliptician::importance::AutoWeight;
+use elliptician::painter::{absolute_path, levels, resize_target};
+use elliptician::palette::Palette;
+use elliptician::shape::ShapeType;
+use elliptician::svg::{load_svg, save_svg, svg_string};
+use elliptician::{avg_color, Model, Optimizer, Painter, PainterOptions, Placement, Strategy};
 
-fn try_draw(
-    color_converter: &ColorConverter,
-    pixmap: &mut Pixmap,
-    tmp_target: &mut Pixmap,
-    lab_img: &[Lab],
-    cmd: &DrawCommand,
-    mse_ratio: f32,
-) -> f32 {
-    copy_img(pixmap, tmp_target);
-    draw_cmd(tmp_target, &cmd, true);
-    let score = dssim::diff(&color_converter, &lab_img, tmp_target, mse_ratio);
-    score
-}
-
-fn draw_bg(pixmap: &mut Pixmap, bg_color_string: &str, w: u32, h: u32, img: &[u8]) -> ColorU8 {
+fn parse_bg_color(bg_color_string: &str, w: u32, h: u32, img: &[u8]) -> ColorU8 {
     println!("bg_color_string:{:?}", &bg_color_string);
-    let bg_color;
-    if bg_color_string == "avg" {
-        bg_color = avg_color(w, h, &img);
+    let bg_color = if bg_color_string == "avg" {
+        avg_color(w, h, img)
     } else {
         let rgb = read_color::rgb(&mut bg_color_string.chars()).unwrap();
-        bg_color = ColorU8::from_rgba(rgb[0], rgb[1], rgb[2], 0xff);
-    }
+        ColorU8::from_rgba(rgb[0], rgb[1], rgb[2], 0xff)
+    };
     println!("bg_color:{:?}", &bg_color);
-    let w = pixmap.width();
-    let h = pixmap.height();
-    let mut paint = Paint::default();
-    paint.set_color_rgba8(
-        bg_color.red(),
-        bg_color.green(),
-        bg_color.blue(),
-        bg_color.alpha(),
-    );
-    let rect = Rect::from_ltrb(0.0, 0.0, w as f32, h as f32).unwrap();
-    pixmap.fill_rect(rect, &paint, Transform::identity(), None);
     bg_color
 }
 
-fn pixmap_from_vec(w: u32, h: u32, img_data: &mut Vec<u8>) -> Pixmap {
-    //Pixmap::from_vec(img_data, IntSize::from_wh(w, h)).unwrap()
-    let mut pixmap = Pixmap::new(w, h).unwrap();
-
-    let data = pixmap.data_mut();
-
-    assert_eq!(data.len(), img_data.len());
-
-    for i in 0..data.len() {
-        data[i] = img_data[i];
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut args = pico_args::Arguments::from_env();
+    let path = args
+        .opt_value_from_str(["--path", "-p"])?
+        .unwrap_or_else(|| "examples/monalisa_s.jpg".to_string());
+    let num: Option<u32> = args.opt_value_from_str(["--num", "-n"])?;
+    let alpha: Option<u8> = args.opt_value_from_str(["--alpha", "-a"])?;
+    let alpha_min: Option<u8> = args.opt_value_from_str("--alpha-min")?;
+    let alpha_max: Option<u8> = args.opt_value_from_str("--alpha-max")?;
+    let brush_scale: Option<f32> = args.opt_value_from_str(["--brush-scale", "-b"])?;
+    let bg_color_string: Option<String> = args.opt_value_from_str(["--bg-color", "-bg"])?;
+    let seed_count: Option<u32> = args.opt_value_from_str(["--seed-count", "-s"])?;
+    let optimize_count: Option<u32> = args.opt_value_from_str(["--optimize-count", "-o"])?;
+    let mse_ratio: Option<f32> = args.opt_value_from_str(["--mse-ratio", "-m"])?;
+    let color_mode: Option<ColorMode> = args.opt_value_from_str("--color-mode")?;
+    let svg_precision = args.opt_value_from_str("--svg-precision")?.unwrap_or(2);
+    let svg_minify = args.contains("--svg-minify");
+    let shape: Option<ShapeType> = args.opt_value_from_str("--shape")?;
+    let seed: Option<u64> = args.opt_value_from_str("--seed")?;
+    let checkpoint_path = args
+        .opt_value_from_str("--checkpoint")?
+        .unwrap_or_else(|| "checkpoint.txt".to_string());
+    let checkpoint_interval: u32 = args
+        .opt_value_from_str("--checkpoint-interval")?
+        .unwrap_or(10);
+    let shape_count: Option<usize> = args.opt_value_from_str("--shape-count")?;
+    let max_svg_bytes: Option<usize> = args.opt_value_from_str("--max-svg-bytes")?;
+    let overshoot: f32 = args.opt_value_from_str("--overshoot")?.unwrap_or(0.25);
+    let refine_count = args.opt_value_from_str("--refine-count")?.unwrap_or(8);
+    let resume: Option<String> = args.opt_value_from_str("--resume")?;
+    let init_svg: Option<String> = args.opt_value_from_str("--init-svg")?;
+    let palette_spec: Option<String> = args.opt_value_from_str("--palette")?;
+    let work_size: Option<u32> = args.opt_value_from_str("--work-size")?;
+    let pyramid: Option<u32> = args.opt_value_from_str("--pyramid")?;
+    let weight_mask: Option<String> = args.opt_value_from_str("--weight-mask")?;
+    let auto_weight: Option<AutoWeight> = args.opt_value_from_str("--auto-weight")?;
+    let placement: Option<Placement> = args.opt_value_from_str("--placement")?;
+    let orient = args.contains("--orient");
+    let strategy: Option<Strategy> = args.opt_value_from_str("--optimizer")?;
+    let temperature: Option<f32> = args.opt_value_from_str("--temperature")?;
+    let cooling: Option<f32> = args.opt_value_from_str("--cooling")?;
+
+    if resume.is_some() && init_svg.is_some() {
+        return Err("--resume and --init-svg cannot be used together".into());
     }
-    pixmap
-}
-
-fn vec_from_pixmap(pixmap_mutex: &Mutex<Pixmap>) -> Vec<u8> {
-    let mut pixmap = pixmap_mutex.lock().unwrap();
-    let mut pixmap_data: Vec<u8> = Vec::new();
-
-    let data = pixmap.data_mut();
 
-    for i in 0..data.len() {
-        pixmap_data.push(data[i]);
+    // the checkpoint keeps the colors it was started with
+    if resume.is_some() && (palette_spec.is_some() || bg_color_string.is_some()) {
+        return Err("--resume cannot be used with --palette or --bg-color".into());
     }
 
-    pixmap_data
-}
-
-fn get_color_string(color: &ColorU8) -> String {
-    if color.is_opaque() {
-        format!(
-            "#{:02x}{:02x}{:02x}",
-            color.red(),
-            color.green(),
-            color.blue()
-        )
-    } else {
-        format!(
-            "#{:02x}{:02x}{:02x}{:02x}",
-            color.red(),
-            color.green(),
-            color.blue(),
-            color.alpha()
-        )
+    if weight_mask.is_some() && auto_weight.is_some() {
+        return Err("--weight-mask and --auto-weight cannot be used together".into());
     }
-}
-fn save_svg(w: u32, h: u32, svg_name: &str, commands: &[DrawCommand], bg_color: &ColorU8) {
-    let header = format!(
-        r#"<svg version="1.1" width="{}" height="{}" xmlns="http://www.w3.org/2000/svg">"#,
-        w, h
-    );
-    let footer = "</svg>";
-
-    let mut data = String::from(header);
-    data.push('\n');
 
-    let bg = format!(
-        r#"<rect width="100%" height="100%" fill="{}" />"#,
-        get_color_string(bg_color)
-    );
-    data.push_str(&bg);
-    data.push('\n');
-
-    for i in 0..commands.len() {
-        let command = commands[i];
-        let s = format!(
-            r#"<g transform="translate({},{})"><ellipse rx="{}" ry="{}" fill="{}" transform="rotate({})"/></g>"#,
-            command.x,
-            command.y,
-            command.rx,
-            command.ry,
-            get_color_string(&command.color),
-            command.angle
-        );
-        data.push_str(&s);
-        data.push('\n');
+    if pyramid == Some(0) {
+        return Err("--pyramid must be at least 1".into());
     }
 
-    data.push_str(footer);
-    data.push('\n');
-
-    let mut f = fs::File::create(svg_name).unwrap();
-    f.write_all(data.as_bytes()).unwrap();
-}
+    if work_size == Some(0) {
+        return Err("--work-size must be at least 1".into());
+    }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let mut args = pico_args::Arguments::from_env();
-    let path = args
-        .opt_value_from_str(["--path", "-p"])?
-        .unwrap_or("examples/monalisa_s.jpg".to_string());
-    let num = args.opt_value_from_str(["--num", "-n"])?.unwrap_or(1000);
-    let alpha = args.opt_value_from_str(["--alpha", "-a"])?.unwrap_or(128);
-    let brush_scale = args
-        .opt_value_from_str(["--brush-scale", "-b"])?
-        .unwrap_or(0.75);
-    let bg_color_string = args
-        .opt_value_from_str(["--bg-color", "-bg"])?
-        .unwrap_or("avg".to_string());
-    let seed_count = args
-        .opt_value_from_str(["--seed-count", "-s"])?
-        .unwrap_or(32);
-    let optimize_count = args
-        .opt_value_from_str(["--optimize-count", "-o"])?
-        .unwrap_or(64);
-    let mse_ratio = args
-        .opt_value_from_str(["--mse-ratio", "-m"])?
-        .unwrap_or(0.1);
+    if temperature.is_some_and(|t| t < 0.0) || cooling.is_some_and(|c| !(c > 0.0 && c <= 1.0)) {
+        return Err("--temperature must not be negative and --cooling must be in (0, 1]".into());
+    }
 
     let img = image::open(path).unwrap().to_rgba8();
     let w = img.width();
     let h = img.height();
     println!("{}x{}", w, h);
-    let img_raw = img.into_raw();
-
-    let color_converter = ColorConverter::new();
-
-    let lab_img = color_converter.lab_image(w, h, &img_raw);
-
-    let mut global_best_score;
-
-    let pixmap = Pixmap::new(w, h).unwrap();
 
-    let pixmap_mutex = Mutex::new(pixmap);
-    let bg_color;
-    {
-        let mut pixmap = pixmap_mutex.lock().unwrap();
-        bg_color = draw_bg(&mut pixmap, &bg_color_string, w, h, &img_raw);
-        global_best_score = dssim::diff(&color_converter, &lab_img, &mut pixmap, mse_ratio);
+    let mut painter = if let Some(resume_path) = resume {
+        // options come from the checkpoint, --num moves the end of the run
+        let mut checkpoint = Checkpoint::load(&resume_path)?;
+        if let Some(num) = num {
+            checkpoint.optimizer.num = num;
+        }
+        let o = &checkpoint.optimizer;
+        let p = &checkpoint.painter;
+        let check = |flag: &str, differs: bool| -> Result<(), String> {
+            if differs {
+                return Err(format!("{} was not run with that {}", resume_path, flag));
+            }
+            Ok(())
+        };
+        let pinned = alpha.map(|a| (alpha_min.unwrap_or(a), alpha_max.unwrap_or(a)));
+        check(
+            "--alpha",
+            pinned.is_some_and(|a| a != (o.alpha_min, o.alpha_max)),
+        )?;
+        check("--alpha-min", alpha_min.is_some_and(|a| a != o.alpha_min))?;
+        check("--alpha-max", alpha_max.is_some_and(|a| a != o.alpha_max))?;
+        check(
+            "--brush-scale",
+            brush_scale.is_some_and(|b| b != o.brush_scale),
+        )?;
+        check(
+            "--seed-count",
+            seed_count.is_some_and(|c| c != o.seed_count),
+        )?;
+        check(
+            "--optimize-count",
+            optimize_count.is_some_and(|c| c != o.optimize_count),
+        )?;
+        check("--mse-ratio", mse_ratio.is_some_and(|r| r != o.mse_ratio))?;
+        check(
+            "--color-mode",
+            color_mode.is_some_and(|m| m != o.color_mode),
+        )?;
+        check("--shape", shape.is_some_and(|s| s != o.shape))?;
+        check("--seed", seed.is_some_and(|s| s != o.seed))?;
+        check("--placement", placement.is_some_and(|pl| pl != o.placement))?;
+        check("--orient", orient && !o.orient)?;
+        check(
+            "--auto-weight",
+            auto_weight.is_some() && auto_weight != o.auto_weight,
+        )?;
+        check("--optimizer", strategy.is_some_and(|s| s != o.strategy))?;
+        check(
+            "--temperature",
+            temperature.is_some_and(|t| t != o.temperature),
+        )?;
+        check("--cooling", cooling.is_some_and(|c| c != o.cooling))?;
+        check(
+            "--work-size",
+            work_size.is_some() && work_size != p.work_size,
+        )?;
+        check("--pyramid", pyramid.is_some_and(|n| n != p.pyramid))?;
+        let weight_mask = weight_mask.as_deref().map(absolute_path).transpose()?;
+        check(
+            "--weight-mask",
+            weight_mask.is_some() && weight_mask != p.weight_mask,
+        )?;
+        println!("resume:{} iteration:{}", resume_path, checkpoint.iteration);
+        Painter::from_checkpoint(img, &checkpoint).map_err(|e| format!("{}: {}", resume_path, e))?
+    } else {
+        let color_mode = color_mode.unwrap_or(ColorMode::Solve);
+        // --alpha pins the alpha of every shape and sampled colors keep 128,
+        // otherwise alpha is searched within the bounds
+        let sampled = color_mode == ColorMode::Sample && alpha_min.is_none() && alpha_max.is_none();
+        let (alpha_min, alpha_max) = match alpha {
+            Some(alpha) => (alpha_min.unwrap_or(alpha), alpha_max.unwrap_or(alpha)),
+            None if sampled => (128, 128),
+            None => (alpha_min.unwrap_or(1), alpha_max.unwrap_or(255)),
+        };
+        if alpha_min > alpha_max {
+            return Err("--alpha-min must not be greater than --alpha-max".into());
+        }
+        let seed = seed.unwrap_or_else(rand::random);
+        let pyramid = pyramid.unwrap_or(1);
+        let svg_image = match init_svg {
+            Some(ref svg_path) => {
+                let svg_image = load_svg(svg_path)?;
+                if svg_image.w != w || svg_image.h != h {
+                    let size = format!("{}x{}", svg_image.w, svg_image.h);
+                    return Err(format!("{} is a {} image", svg_path, size).into());
+                }
+                println!(
+                    "init_svg:{} commands:{}",
+                    svg_path,
+                    svg_image.commands.len()
+                );
+                Some(svg_image)
+            }
+            None => None,
+        };
+        let bg_color = match svg_image {
+            Some(ref svg_image) => svg_image.bg_color,
+            None => {
+                // the color is taken from the target of the first level
+                let dims = levels(w, h, work_size, pyramid)[0];
+                let target = resize_target(&img, dims);
+                let bg_color_string = bg_color_string.as_deref().unwrap_or("avg");
+                parse_bg_color(bg_color_string, dims.0, dims.1, &target)
+            }
+        };
+
+        let optimizer = Optimizer {
+            num: num.unwrap_or(1000),
+            alpha_min,
+            alpha_max,
+            brush_scale: brush_scale.unwrap_or(0.75),
+            seed_count: seed_count.unwrap_or(32),
+            optimize_count: optimize_count.unwrap_or(64),
+            mse_ratio: mse_ratio.unwrap_or(0.1),
+            shape: shape.unwrap_or(ShapeType::Ellipse),
+            color_mode,
+            seed,
+            palette: None,
+            placement: placement.unwrap_or(Placement::Uniform),
+            orient,
+            auto_weight,
+            strategy: strategy.unwrap_or(Strategy::Hill),
+            temperature: temperature.unwrap_or(0.001),
+            cooling: cooling.unwrap_or(0.95),
+        };
+        let options = PainterOptions {
+            work_size,
+            pyramid,
+            weight_mask,
+        };
+        let mut painter = Painter::new(img, bg_color, optimizer, options)?;
+        // web216, kmeans:N (extracted from the target) or a file of hex colors
+        let palette = match palette_spec.as_deref() {
+            None => None,
+            Some("web216") => Some(Palette::web216()),
+            Some(spec) => match spec.strip_prefix("kmeans:") {
+                Some(count) => match count.parse::<usize>() {
+                    Ok(count) if count > 0 => {
+                        let model = painter.model();
+                        Some(Palette::kmeans(
+                            model.color_converter(),
+                            model.target(),
+                            count,
+                            seed,
+                        ))
+                    }
+                    _ => return Err(format!("invalid palette: {}", spec).into()),
+                },
+                None => Some(Palette::load(spec)?),
+            },
+        };
+        if let Some(ref palette) = palette {
+            println!("palette:{}", palette);
+        }
+        painter.model_mut().set_palette(palette);
+        if let Some(svg_image) = svg_image {
+            painter.push_commands(&svg_image.commands);
+        }
+        painter
+    };
+    for &(level_w, level_h) in painter.levels().iter().filter(|&&dims| dims != (w, h)) {
+        println!("work size:{}x{}", level_w, level_h);
     }
+    let bg_color = painter.model().bg_color();
+    println!("seed:{}", painter.model().optimizer().seed);
+    let num = painter.model().optimizer().num;
+    let shape = painter.model().optimizer().shape;
+
+    // with --shape-count, keep placing shapes past --num (up to twice as many
+    // iterations) until there are enough to prune from
+    let prune_from = shape_count.map(|n| (n as f32 * (1.0 + overshoot)).ceil() as usize);
+    let needs_more = |model: &Model| match prune_from {
+        Some(prune_from) => model.commands().len() < prune_from && model.iteration() < 2 * num,
+        None => false,
+    };
+
+    // with --max-svg-bytes, stop once the svg is well over the budget
+    let over_budget = |painter: &Painter| match max_svg_bytes {
+        Some(max_svg_bytes) => {
+            let commands = painter.commands();
+            let size = svg_string(w, h, &commands, &bg_color, svg_precision, svg_minify).len();
+            size as f32 > max_svg_bytes as f32 * (1.0 + overshoot)
+        }
+        None => false,
+    };
 
-    let mut commands = Vec::new();
-
-    for t in 0..num {
-        let t_ratio = (t as f32) / (num as f32);
+    while (painter.model().iteration() < num || needs_more(painter.model()))
+        && !over_budget(&painter)
+    {
+        let level = painter.level();
+        let global_best_score = painter.model().score();
         let start = Instant::now();
 
-        let results: Vec<(f32, DrawCommand)> = (0..seed_count)
-            .into_par_iter()
-            .map(|_| {
-                let mut rng = rand::thread_rng();
-
-                let mut tmp_pixmap = Pixmap::new(w, h).unwrap();
-
-                let mut pixmap_data = vec_from_pixmap(&pixmap_mutex);
-
-                let mut src_target = pixmap_from_vec(w, h, &mut pixmap_data);
-
-                let mut best_cmd =
-                    DrawCommand::rand(w, h, t_ratio, &img_raw, &mut rng, alpha, brush_scale);
-                let mut best_score = try_draw(
-                    &color_converter,
-                    &mut src_target,
-                    &mut tmp_pixmap,
-                    &lab_img,
-                    &best_cmd,
-                    mse_ratio,
-                );
-
-                // optimize
-                for _j in 0..optimize_count {
-                    let (cmd, cmd2) =
-                        DrawCommand::mutate(w, h, t_ratio, &best_cmd, &mut rng, brush_scale);
-                    let score;
-                    if cmd == best_cmd {
-                        score = best_score;
-                    } else {
-                        score = try_draw(
-                            &color_converter,
-                            &mut src_target,
-                            &mut tmp_pixmap,
-                            &lab_img,
-                            &cmd,
-                            mse_ratio,
-                        );
-                    }
-                    if score < best_score {
-                        best_score = score;
-                        best_cmd = cmd;
-                    } else if cmd != cmd2 {
-                        let score2;
-                        if cmd2 == best_cmd {
-                            score2 = best_score;
-                        } else {
-                            score2 = try_draw(
-                                &color_converter,
-                                &mut src_target,
-                                &mut tmp_pixmap,
-                                &lab_img,
-                                &cmd2,
-                                mse_ratio,
-                            );
-                        }
-                        if score2 < best_score {
-                            best_score = score2;
-                            best_cmd = cmd2;
-                        }
-                    }
-                }
-
-                (best_score, best_cmd)
-            })
-            .collect();
-
-        let mut best_score = 0.0_f32;
-        let mut best_cmd = DrawCommand::new();
-        for i in 0..results.len() {
-            let (score, cmd) = results[i as usize];
-            if i == 0 || score < best_score {
-                best_score = score;
-                best_cmd = cmd;
-            }
-        }
+        let step = painter.step();
+        let t = step.iteration;
 
         let duration = start.elapsed();
+        if painter.level() != level {
+            println!("level : {}x{}", painter.level().0, painter.level().1);
+        }
         println!(
             "{} : {} {} {:?}",
-            t, global_best_score, best_score, duration
+            t, global_best_score, step.score, duration
         );
 
-        if best_score < global_best_score {
+        if let Some(best_cmd) = step.command {
             println!("   {:?}", &best_cmd);
-            commands.push(best_cmd);
-            global_best_score = best_score;
-            let mut pixmap = pixmap_mutex.lock().unwrap();
-            //draw best cmd
-            draw_cmd(&mut pixmap, &best_cmd, true);
+            if shape == ShapeType::Any {
+                let counts: Vec<String> = painter
+                    .model()
+                    .shape_counts()
+                    .iter()
+                    .map(|(shape_type, count)| format!("{}:{}", shape_type, count))
+                    .collect();
+                println!("   {}", counts.join(" "));
+            }
         }
 
-        {
-            let pixmap = pixmap_mutex.lock().unwrap();
-            let img_name = format!("result_{:06}.png", t);
-            pixmap.save_png(img_name).unwrap();
-        }
+        let img_name = format!("result_{:06}.png", t);
+        painter.canvas().save_png(img_name).unwrap();
 
         let svg_name = format!("result_{:06}.svg", t);
-        save_svg(w, h, &svg_name, &commands, &bg_color);
+        save_svg(
+            w,
+            h,
+            &svg_name,
+            &painter.commands(),
+            &bg_color,
+            svg_precision,
+            svg_minify,
+        );
+
+        if checkpoint_interval > 0 && (t + 1) % checkpoint_interval == 0 {
+            painter.checkpoint().save(&checkpoint_path)?;
+        }
     }
 
-    {
-        let pixmap = pixmap_mutex.lock().unwrap();
-        pixmap.save_png("out.png").unwrap();
+    let level = painter.level();
+    painter.finish_levels();
+    if painter.level() != level {
+        println!("level : {}x{}", painter.level().0, painter.level().1);
     }
 
+    if let Some(shape_count) = shape_count {
+        for (count, score) in painter.prune(shape_count) {
+            println!("prune : {} {}", count, score);
+        }
+        if painter.model().commands().len() < shape_count {
+            println!(
+                "only {} shapes were placed",
+                painter.model().commands().len()
+            );
+        }
+    }
+
+    // removing commands would break an exact --shape-count
+    let score = painter.model().score();
+    let start = Instant::now();
+    let refinement = painter.refine(refine_count, shape_count.is_none());
+    println!(
+        "refine : {} {} removed:{} improved:{} reordered:{} {:?}",
+        score,
+        painter.model().score(),
+        refinement.removed,
+        refinement.improved,
+        refinement.reordered,
+        start.elapsed()
+    );
+
+    if let Some(max_svg_bytes) = max_svg_bytes {
+        for (bytes, score) in painter.prune_to_bytes(max_svg_bytes, svg_precision, svg_minify) {
+            println!("prune : {}B {}", bytes, score);
+        }
+    }
+    painter.checkpoint().save(&checkpoint_path)?;
+
+    painter.canvas().save_png("out.png").unwrap();
+    save_svg(
+        w,
+        h,
+        "out.svg",
+        &painter.commands(),
+        &bg_color,
+        svg_precision,
+        svg_minify,
+    );
+
     Ok(())
 }
 
@@ -357,13 +392,7 @@ todo
 paint changed location in alpha white -> score weight
 parse outputpath
 
-resize in calc, save in original size
-
 write command
 optimize svg
- quantize color(web216), size
- global optimize
-  remove unneeded cmd
-  mutate cmd
 
 */
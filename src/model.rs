@@ -0,0 +1,1261 @@
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use lab::Lab;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use tiny_skia::*;
+
+use rayon::prelude::*;
+
+use crate::checkpoint::Checkpoint;
+use crate::color_solver::{solve_color, solve_color_alpha, ColorMode};
+use crate::coverage::Coverage;
+use crate::dssim::{Scorer, BLOCK_SIZE};
+use crate::importance::{auto_weights, AutoWeight};
+use crate::orientation::Orientation;
+use crate::painter::PainterOptions;
+use crate::palette::Palette;
+use crate::renderer::{fill_coverage, fill_coverage_clipped};
+use crate::shape::{Brush, ShapeType};
+use crate::svg::{command_size, shared_alpha, svg_string};
+use crate::ColorConverter;
+use crate::DrawCommand;
+
+pub fn avg_color(w: u32, h: u32, img: &[u8]) -> ColorU8 {
+    let mut sum_r = 0;
+    let mut sum_g = 0;
+    let mut sum_b = 0;
+    for y in 0..h {
+        for x in 0..w {
+            let index2 = (x + w * y) as usize;
+            let index = 4 * index2;
+            let r = img[index] as u32;
+            let g = img[index + 1] as u32;
+            let b = img[index + 2] as u32;
+
+            sum_r += r;
+            sum_g += g;
+            sum_b += b;
+        }
+    }
+    let count = w * h;
+    if count == 0 {
+        return ColorU8::from_rgba(0, 0, 0, 0xff);
+    }
+    let r = (sum_r / count) as u8;
+    let g = (sum_g / count) as u8;
+    let b = (sum_b / count) as u8;
+
+    ColorU8::from_rgba(r, g, b, 0xff)
+}
+
+fn copy_region(src: &Pixmap, dst: &mut Pixmap, rect: (u32, u32, u32, u32)) {
+    let w = src.width() as usize;
+    let (x0, y0, x1, y1) = rect;
+    let src_data = src.data();
+    let dst_data = dst.data_mut();
+    for y in y0 as usize..y1 as usize {
+        let start = 4 * (x0 as usize + w * y);
+        let end = 4 * (x1 as usize + w * y);
+        dst_data[start..end].copy_from_slice(&src_data[start..end]);
+    }
+}
+
+fn union_rect(
+    a: Option<(u32, u32, u32, u32)>,
+    b: Option<(u32, u32, u32, u32)>,
+) -> Option<(u32, u32, u32, u32)> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some((a.0.min(b.0), a.1.min(b.1), a.2.max(b.2), a.3.max(b.3))),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
+fn intersect_rect(
+    a: (u32, u32, u32, u32),
+    b: (u32, u32, u32, u32),
+) -> Option<(u32, u32, u32, u32)> {
+    let rect = (a.0.max(b.0), a.1.max(b.1), a.2.min(b.2), a.3.min(b.3));
+    if rect.0 < rect.2 && rect.1 < rect.3 {
+        Some(rect)
+    } else {
+        None
+    }
+}
+
+// `commands` drawn by tiny-skia over `bg_color`, e.g. to render them scaled up
+// to the input size
+pub fn render(w: u32, h: u32, bg_color: &ColorU8, commands: &[DrawCommand]) -> Pixmap {
+    let mut pixmap = Pixmap::new(w, h).unwrap();
+    draw_bg(&mut pixmap, bg_color);
+    for cmd in commands.iter() {
+        cmd.draw(&mut pixmap, true);
+    }
+    pixmap
+}
+
+fn draw_bg(pixmap: &mut Pixmap, bg_color: &ColorU8) {
+    let w = pixmap.width();
+    let h = pixmap.height();
+    let mut paint = Paint::default();
+    paint.set_color_rgba8(
+        bg_color.red(),
+        bg_color.green(),
+        bg_color.blue(),
+        bg_color.alpha(),
+    );
+    let rect = Rect::from_ltrb(0.0, 0.0, w as f32, h as f32).unwrap();
+    pixmap.fill_rect(rect, &paint, Transform::identity(), None);
+}
+
+// streams of generators that never share a key
+const CANDIDATE_STREAM: u32 = 0;
+const REFINE_STREAM: u32 = 1;
+
+fn stream_rng(seed: u64, stream: u32, iteration: u32, index: u32) -> StdRng {
+    let mut key = [0_u8; 32];
+    key[0..8].copy_from_slice(&seed.to_le_bytes());
+    key[8..12].copy_from_slice(&iteration.to_le_bytes());
+    key[12..16].copy_from_slice(&index.to_le_bytes());
+    key[16..20].copy_from_slice(&stream.to_le_bytes());
+    StdRng::from_seed(key)
+}
+
+// independent generator for candidate `index` of iteration `iteration`
+fn candidate_rng(seed: u64, iteration: u32, index: u32) -> StdRng {
+    stream_rng(seed, CANDIDATE_STREAM, iteration, index)
+}
+
+// generator for layer `index` of a refine pass after `iteration`
+fn refine_rng(seed: u64, iteration: u32, index: u32) -> StdRng {
+    stream_rng(seed, REFINE_STREAM, iteration, index)
+}
+
+// per-thread buffers kept across iterations, `mask` is transparent
+// whenever the scratch is not in use
+struct Scratch {
+    mask: Pixmap,
+    coverage: Coverage,
+}
+
+// where new candidates are created
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Placement {
+    // uniformly, or in proportion to the weights if set
+    Uniform,
+    // in proportion to the current per-pixel error
+    Error,
+}
+
+impl Placement {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Placement::Uniform => "uniform",
+            Placement::Error => "error",
+        }
+    }
+}
+
+impl fmt::Display for Placement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Placement {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Placement, String> {
+        match s {
+            "uniform" => Ok(Placement::Uniform),
+            "error" => Ok(Placement::Error),
+            _ => Err(format!("unknown placement: {}", s)),
+        }
+    }
+}
+
+// how a candidate is improved after it is created
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Strategy {
+    // only keeps strict improvements
+    Hill,
+    // simulated annealing, sometimes keeps a worse mutation while the
+    // temperature is high
+    Anneal,
+}
+
+impl Strategy {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Strategy::Hill => "hill",
+            Strategy::Anneal => "anneal",
+        }
+    }
+}
+
+impl fmt::Display for Strategy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Strategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Strategy, String> {
+        match s {
+            "hill" => Ok(Strategy::Hill),
+            "anneal" => Ok(Strategy::Anneal),
+            _ => Err(format!("unknown optimizer: {}", s)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Optimizer {
+    pub num: u32,
+    pub alpha_min: u8,
+    pub alpha_max: u8,
+    pub brush_scale: f32,
+    pub seed_count: u32,
+    pub optimize_count: u32,
+    pub mse_ratio: f32,
+    pub shape: ShapeType,
+    pub color_mode: ColorMode,
+    pub seed: u64,
+    pub palette: Option<Palette>,
+    pub placement: Placement,
+    // start ellipses and rotated rectangles along the target's structure
+    pub orient: bool,
+    // weights computed from the target, unless `Model::set_weights` replaces
+    // them
+    pub auto_weight: Option<AutoWeight>,
+    pub strategy: Strategy,
+    // annealing only: start temperature relative to the current score, and
+    // what it is multiplied by after each mutation
+    pub temperature: f32,
+    pub cooling: f32,
+}
+
+impl Default for Optimizer {
+    fn default() -> Optimizer {
+        Optimizer {
+            num: 1000,
+            alpha_min: 1,
+            alpha_max: 255,
+            brush_scale: 0.75,
+            seed_count: 32,
+            optimize_count: 64,
+            mse_ratio: 0.1,
+            shape: ShapeType::Ellipse,
+            color_mode: ColorMode::Solve,
+            seed: 0,
+            palette: None,
+            placement: Placement::Uniform,
+            orient: false,
+            auto_weight: None,
+            strategy: Strategy::Hill,
+            temperature: 0.001,
+            cooling: 0.95,
+        }
+    }
+}
+
+impl Optimizer {
+    // alpha values mutations may pick, the solver picks it in SolveAlpha
+    fn alpha_range(&self) -> (u8, u8) {
+        if self.color_mode == ColorMode::SolveAlpha {
+            (self.alpha_max, self.alpha_max)
+        } else {
+            (self.alpha_min, self.alpha_max)
+        }
+    }
+
+    // the color a shape with `coverage` gets over `canvas`, `color` is its
+    // current one
+    fn solve(
+        &self,
+        converter: &ColorConverter,
+        target: &[u8],
+        canvas: &Pixmap,
+        coverage: &Coverage,
+        color: ColorU8,
+    ) -> ColorU8 {
+        let color = match self.color_mode {
+            ColorMode::Sample => color,
+            ColorMode::SolveAlpha => {
+                solve_color_alpha(target, canvas, coverage, self.alpha_min, self.alpha_max)
+            }
+            ColorMode::Solve => solve_color(target, canvas, coverage, color.alpha()),
+        };
+        match self.palette {
+            Some(ref palette) => palette.snap(converter, &color),
+            None => color,
+        }
+    }
+
+    // sets the color of `cmd` for its current geometry and scores it
+    fn evaluate(&self, model: &Model, scratch: &mut Scratch, cmd: &mut DrawCommand) -> f32 {
+        scratch
+            .coverage
+            .rasterize_into(&cmd.shape, &mut scratch.mask);
+        let coverage = &scratch.coverage;
+        if coverage.is_empty() {
+            return model.score;
+        }
+        cmd.color = self.solve(
+            &model.color_converter,
+            &model.target,
+            &model.canvas,
+            coverage,
+            cmd.color,
+        );
+        model.scorer.score_coverage(
+            &model.color_converter,
+            &model.lab_img,
+            &model.canvas,
+            coverage,
+            &cmd.color,
+        )
+    }
+
+    // whether a mutation scoring `score` replaces the one scoring `current`
+    fn accept(&self, score: f32, current: f32, temperature: f32, rng: &mut StdRng) -> bool {
+        if score < current {
+            return true;
+        }
+        match self.strategy {
+            Strategy::Hill => false,
+            Strategy::Anneal => {
+                temperature > 0.0 && rng.gen::<f32>() < (-(score - current) / temperature).exp()
+            }
+        }
+    }
+
+    fn search(&self, model: &Model, t: u32, t_ratio: f32) -> (f32, DrawCommand) {
+        let w = model.w;
+        let h = model.h;
+        let brush = Brush::new(w, h, t_ratio, self.brush_scale);
+        let mutate_color = self.color_mode == ColorMode::Sample;
+        let alpha_range = self.alpha_range();
+
+        let results: Vec<(f32, DrawCommand)> = (0..self.seed_count)
+            .into_par_iter()
+            .map(|i| {
+                let mut rng = candidate_rng(self.seed, t, i);
+                let mut scratch = model.take_scratch();
+
+                let palette = self.palette.as_ref();
+                let position = match self.placement {
+                    Placement::Uniform => model.scorer.sample_weight(&mut rng),
+                    Placement::Error => Some(model.scorer.sample_error(&mut rng)),
+                };
+                let mut best_cmd = DrawCommand::rand(
+                    self.shape,
+                    &brush,
+                    &model.target,
+                    &mut rng,
+                    alpha_range,
+                    palette,
+                    &model.color_converter,
+                    position,
+                    model.orientation.as_ref(),
+                );
+                let mut best_score = self.evaluate(model, &mut scratch, &mut best_cmd);
+
+                // the command being mutated, it only falls behind the best
+                // one when annealing
+                let mut current_cmd = best_cmd;
+                let mut current_score = best_score;
+                let mut temperature = self.temperature * model.score;
+
+                // optimize
+                for _j in 0..self.optimize_count {
+                    let (mut cmd, mut cmd2) = DrawCommand::mutate(
+                        &brush,
+                        &current_cmd,
+                        &mut rng,
+                        mutate_color,
+                        alpha_range,
+                        palette,
+                    );
+                    let score = if cmd == current_cmd {
+                        current_score
+                    } else {
+                        self.evaluate(model, &mut scratch, &mut cmd)
+                    };
+                    if self.accept(score, current_score, temperature, &mut rng) {
+                        current_score = score;
+                        current_cmd = cmd;
+                    } else if cmd != cmd2 {
+                        let score2 = if cmd2 == current_cmd {
+                            current_score
+                        } else {
+                            self.evaluate(model, &mut scratch, &mut cmd2)
+                        };
+                        if self.accept(score2, current_score, temperature, &mut rng) {
+                            current_score = score2;
+                            current_cmd = cmd2;
+                        }
+                    }
+                    if current_score < best_score {
+                        best_score = current_score;
+                        best_cmd = current_cmd;
+                    }
+                    temperature *= self.cooling;
+                }
+
+                model.return_scratch(scratch);
+                (best_score, best_cmd)
+            })
+            .collect();
+
+        let mut best_score = 0.0_f32;
+        let mut best_cmd = DrawCommand::new();
+        for (i, &(score, cmd)) in results.iter().enumerate() {
+            if i == 0 || score < best_score {
+                best_score = score;
+                best_cmd = cmd;
+            }
+        }
+        (best_score, best_cmd)
+    }
+}
+
+// a committed command with its coverage, kept while refining
+struct Layer {
+    cmd: DrawCommand,
+    coverage: Coverage,
+    rect: Option<(u32, u32, u32, u32)>,
+}
+
+// what `Model::refine` changed
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Refinement {
+    pub removed: usize,
+    pub improved: usize,
+    pub reordered: usize,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Step {
+    pub iteration: u32,
+    pub score: f32,
+    pub command: Option<DrawCommand>,
+}
+
+pub struct Model {
+    w: u32,
+    h: u32,
+    target: Vec<u8>,
+    lab_img: Vec<Lab>,
+    color_converter: ColorConverter,
+    canvas: Arc<Pixmap>,
+    scratch: Mutex<Vec<Scratch>>,
+    scorer: Scorer,
+    // per-pixel importance, see `set_weights`
+    weights: Option<Vec<f32>>,
+    // only with `Optimizer::orient`
+    orientation: Option<Orientation>,
+    bg_color: ColorU8,
+    commands: Vec<DrawCommand>,
+    score: f32,
+    iteration: u32,
+    optimizer: Optimizer,
+}
+
+impl Model {
+    pub fn new(w: u32, h: u32, target: Vec<u8>, bg_color: ColorU8, optimizer: Optimizer) -> Model {
+        assert_eq!(target.len(), (4 * w * h) as usize);
+        let color_converter = ColorConverter::new();
+        let lab_img = color_converter.lab_image(w, h, &target);
+
+        let mut canvas = Pixmap::new(w, h).unwrap();
+        draw_bg(&mut canvas, &bg_color);
+        let weights = optimizer
+            .auto_weight
+            .map(|kind| auto_weights(kind, &lab_img, w, h));
+        let scorer = Scorer::new_weighted(
+            &color_converter,
+            &lab_img,
+            &canvas,
+            optimizer.mse_ratio,
+            weights.as_deref(),
+        );
+        let score = scorer.score();
+        let orientation = if optimizer.orient {
+            Some(Orientation::new(&lab_img, w, h))
+        } else {
+            None
+        };
+
+        Model {
+            w,
+            h,
+            target,
+            lab_img,
+            color_converter,
+            canvas: Arc::new(canvas),
+            scratch: Mutex::new(Vec::new()),
+            scorer,
+            weights,
+            orientation,
+            bg_color,
+            commands: Vec::new(),
+            score,
+            iteration: 0,
+            optimizer,
+        }
+    }
+
+    // rebuilds the model a checkpoint was taken from
+    pub fn from_checkpoint(target: Vec<u8>, checkpoint: &Checkpoint) -> Model {
+        let mut model = Model::new(
+            checkpoint.w,
+            checkpoint.h,
+            target,
+            checkpoint.bg_color,
+            checkpoint.optimizer.clone(),
+        );
+        model.push_commands(&checkpoint.commands);
+        model.iteration = checkpoint.iteration;
+        model
+    }
+
+    // draws `commands` over the canvas as if they had been committed
+    pub fn push_commands(&mut self, commands: &[DrawCommand]) {
+        for cmd in commands.iter() {
+            self.draw_command(cmd);
+            self.commands.push(*cmd);
+        }
+        self.rescore();
+    }
+
+    // weights each pixel (0.0 - 1.0) in the score and in where uniformly
+    // placed candidates start. Not carried over by `resized`, unlike
+    // `Optimizer::auto_weight`, which None goes back to.
+    pub fn set_weights(&mut self, weights: Option<Vec<f32>>) {
+        if let Some(ref weights) = weights {
+            assert_eq!(weights.len(), (self.w * self.h) as usize);
+        }
+        self.weights = weights.or_else(|| {
+            let (w, h) = (self.w, self.h);
+            let lab_img = &self.lab_img;
+            self.optimizer
+                .auto_weight
+                .map(|kind| auto_weights(kind, lab_img, w, h))
+        });
+        self.rescore();
+    }
+
+    fn rescore(&mut self) {
+        self.scorer = Scorer::new_weighted(
+            &self.color_converter,
+            &self.lab_img,
+            &self.canvas,
+            self.optimizer.mse_ratio,
+            self.weights.as_deref(),
+        );
+        self.score = self.scorer.score();
+    }
+
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            w: self.w,
+            h: self.h,
+            iteration: self.iteration,
+            bg_color: self.bg_color,
+            optimizer: self.optimizer.clone(),
+            painter: PainterOptions::default(),
+            commands: self.commands.clone(),
+        }
+    }
+
+    // this model continued on `target` of another size, with the commands
+    // scaled to it
+    pub fn resized(&self, w: u32, h: u32, target: Vec<u8>) -> Model {
+        let scale = w.max(h) as f32 / self.w.max(self.h) as f32;
+        let mut checkpoint = self.checkpoint();
+        checkpoint.w = w;
+        checkpoint.h = h;
+        for cmd in checkpoint.commands.iter_mut() {
+            *cmd = cmd.scaled(scale);
+        }
+        Model::from_checkpoint(target, &checkpoint)
+    }
+
+    pub fn width(&self) -> u32 {
+        self.w
+    }
+
+    pub fn height(&self) -> u32 {
+        self.h
+    }
+
+    pub fn target(&self) -> &[u8] {
+        &self.target
+    }
+
+    pub fn canvas(&self) -> &Pixmap {
+        &self.canvas
+    }
+
+    pub fn bg_color(&self) -> ColorU8 {
+        self.bg_color
+    }
+
+    pub fn commands(&self) -> &[DrawCommand] {
+        &self.commands
+    }
+
+    pub fn score(&self) -> f32 {
+        self.score
+    }
+
+    pub fn iteration(&self) -> u32 {
+        self.iteration
+    }
+
+    pub fn shape_counts(&self) -> Vec<(ShapeType, usize)> {
+        ShapeType::ALL
+            .iter()
+            .map(|&shape_type| {
+                let count = self
+                    .commands
+                    .iter()
+                    .filter(|cmd| cmd.shape.shape_type() == shape_type)
+                    .count();
+                (shape_type, count)
+            })
+            .collect()
+    }
+
+    pub fn optimizer(&self) -> &Optimizer {
+        &self.optimizer
+    }
+
+    // e.g. to extract a palette in the Lab the scoring uses
+    pub fn color_converter(&self) -> &ColorConverter {
+        &self.color_converter
+    }
+
+    // colors of commands placed from now on, existing ones are kept
+    pub fn set_palette(&mut self, palette: Option<Palette>) {
+        self.optimizer.palette = palette;
+    }
+
+    fn take_scratch(&self) -> Scratch {
+        if let Some(scratch) = self.scratch.lock().unwrap().pop() {
+            return scratch;
+        }
+        Scratch {
+            mask: Pixmap::new(self.w, self.h).unwrap(),
+            coverage: Coverage::new(),
+        }
+    }
+
+    fn return_scratch(&self, scratch: Scratch) {
+        self.scratch.lock().unwrap().push(scratch);
+    }
+
+    // blends `cmd` onto the canvas with the coverage it is scored with,
+    // returns the changed rectangle
+    fn draw_command(&mut self, cmd: &DrawCommand) -> Option<(u32, u32, u32, u32)> {
+        let mut scratch = self.take_scratch();
+        scratch
+            .coverage
+            .rasterize_into(&cmd.shape, &mut scratch.mask);
+        fill_coverage(
+            Arc::make_mut(&mut self.canvas),
+            &scratch.coverage,
+            &cmd.color,
+        );
+        let rect = scratch.coverage.bounds();
+        self.return_scratch(scratch);
+        rect
+    }
+
+    pub fn step(&mut self) -> Step {
+        let t = self.iteration;
+        let t_ratio = ((t as f32) / (self.optimizer.num as f32)).min(1.0);
+
+        let (best_score, best_cmd) = self.optimizer.search(self, t, t_ratio);
+
+        let mut command = None;
+        if best_score < self.score {
+            self.commands.push(best_cmd);
+            //draw best cmd
+            if let Some(rect) = self.draw_command(&best_cmd) {
+                self.scorer
+                    .update_region(&self.color_converter, &self.lab_img, &self.canvas, rect);
+            }
+            self.score = self.scorer.score();
+            command = Some(best_cmd);
+        }
+        self.iteration += 1;
+
+        Step {
+            iteration: t,
+            score: best_score,
+            command,
+        }
+    }
+
+    pub fn run(&mut self, n: u32) {
+        for _ in 0..n {
+            self.step();
+        }
+    }
+
+    fn layer(&self, cmd: DrawCommand) -> Layer {
+        let mut scratch = self.take_scratch();
+        let mut coverage = Coverage::new();
+        coverage.rasterize_into(&cmd.shape, &mut scratch.mask);
+        self.return_scratch(scratch);
+        let rect = coverage.bounds();
+        Layer {
+            cmd,
+            coverage,
+            rect,
+        }
+    }
+
+    // redraws `rect` of `pixmap` from the background and `layers`
+    fn render_region(
+        &self,
+        pixmap: &mut Pixmap,
+        layers: &[Layer],
+        skip: Option<usize>,
+        rect: (u32, u32, u32, u32),
+    ) {
+        let (x0, y0, x1, y1) = rect;
+        let bg = self.bg_color.premultiply();
+        let w = self.w;
+        let pixels = pixmap.pixels_mut();
+        for y in y0..y1 {
+            for pixel in &mut pixels[(x0 + w * y) as usize..(x1 + w * y) as usize] {
+                *pixel = bg;
+            }
+        }
+        for (i, layer) in layers.iter().enumerate() {
+            if skip == Some(i) {
+                continue;
+            }
+            let overlaps = layer.rect.and_then(|r| intersect_rect(r, rect)).is_some();
+            if overlaps {
+                fill_coverage_clipped(pixmap, &layer.coverage, &layer.cmd.color, rect);
+            }
+        }
+    }
+
+    // score with `layers` redrawn inside `rect` of `work`, which must equal
+    // the canvas elsewhere
+    fn score_layers(
+        &self,
+        work: &mut Pixmap,
+        layers: &[Layer],
+        skip: Option<usize>,
+        rect: (u32, u32, u32, u32),
+    ) -> f32 {
+        self.render_region(work, layers, skip, rect);
+        self.scorer
+            .score_region(&self.color_converter, &self.lab_img, work, rect)
+    }
+
+    fn commit_region(&mut self, work: &Pixmap, rect: (u32, u32, u32, u32)) {
+        copy_region(work, Arc::make_mut(&mut self.canvas), rect);
+        self.scorer
+            .update_region(&self.color_converter, &self.lab_img, &self.canvas, rect);
+        self.score = self.scorer.score();
+    }
+
+    // renders `layers` inside `rect` and commits them if the score drops,
+    // `work` must equal the canvas and is left equal to it
+    fn try_layers(
+        &mut self,
+        work: &mut Pixmap,
+        layers: &[Layer],
+        skip: Option<usize>,
+        rect: (u32, u32, u32, u32),
+    ) -> bool {
+        let score = self.score_layers(work, layers, skip, rect);
+        if score < self.score {
+            self.commit_region(work, rect);
+            true
+        } else {
+            copy_region(&self.canvas, work, rect);
+            false
+        }
+    }
+
+    // global pass over the committed commands: drops the ones whose removal
+    // lowers the score (if `remove`), tries `mutate_count` mutations of each
+    // with the rest fixed, then swaps overlapping neighbours
+    pub fn refine(&mut self, mutate_count: u32, remove: bool) -> Refinement {
+        let mut refinement = Refinement::default();
+        let mut layers: Vec<Layer> = self.commands.iter().map(|&cmd| self.layer(cmd)).collect();
+        let mut work = (*self.canvas).clone();
+
+        let mut i = 0;
+        while remove && i < layers.len() {
+            let removed = match layers[i].rect {
+                Some(rect) => self.try_layers(&mut work, &layers, Some(i), rect),
+                None => false,
+            };
+            if removed {
+                layers.remove(i);
+                refinement.removed += 1;
+            } else {
+                i += 1;
+            }
+        }
+
+        let n = layers.len();
+        let mutate_color = self.optimizer.color_mode == ColorMode::Sample;
+        let alpha_range = self.optimizer.alpha_range();
+        // the layers below the one being mutated, to solve its color over
+        let mut under = work.clone();
+        for i in 0..n {
+            // commands were placed with a brush shrinking over the run
+            let t_ratio = i as f32 / n as f32;
+            let brush = Brush::new(self.w, self.h, t_ratio, self.optimizer.brush_scale);
+            let mut rng = refine_rng(self.optimizer.seed, self.iteration, i as u32);
+            let mut improved = false;
+            for _ in 0..mutate_count {
+                let (cmd, _) = DrawCommand::mutate(
+                    &brush,
+                    &layers[i].cmd,
+                    &mut rng,
+                    mutate_color,
+                    alpha_range,
+                    self.optimizer.palette.as_ref(),
+                );
+                if cmd == layers[i].cmd {
+                    continue;
+                }
+                let mut layer = self.layer(cmd);
+                if let Some(rect) = layer.rect {
+                    self.render_region(&mut under, &layers[..i], None, rect);
+                    layer.cmd.color = self.optimizer.solve(
+                        &self.color_converter,
+                        &self.target,
+                        &under,
+                        &layer.coverage,
+                        cmd.color,
+                    );
+                }
+                let rect = match union_rect(layers[i].rect, layer.rect) {
+                    Some(rect) => rect,
+                    None => continue,
+                };
+                let old = std::mem::replace(&mut layers[i], layer);
+                if self.try_layers(&mut work, &layers, None, rect) {
+                    improved = true;
+                } else {
+                    layers[i] = old;
+                }
+            }
+            if improved {
+                refinement.improved += 1;
+            }
+        }
+
+        for i in 1..layers.len() {
+            let rect = match (layers[i - 1].rect, layers[i].rect) {
+                (Some(a), Some(b)) => intersect_rect(a, b),
+                _ => None,
+            };
+            if let Some(rect) = rect {
+                layers.swap(i - 1, i);
+                if self.try_layers(&mut work, &layers, None, rect) {
+                    refinement.reordered += 1;
+                } else {
+                    layers.swap(i - 1, i);
+                }
+            }
+        }
+
+        self.commands = layers.iter().map(|layer| layer.cmd).collect();
+        refinement
+    }
+
+    // removes the command whose removal costs the least score, one at a time,
+    // until `count` are left. Returns the score at each count.
+    pub fn prune(&mut self, count: usize) -> Vec<(usize, f32)> {
+        self.prune_by(|_| 1, count)
+    }
+
+    // like `prune`, but ranks commands by score per byte of svg output and
+    // stops once `svg_string` of the commands scaled to an image of `dims`
+    // fits in `max_bytes`. Returns the svg size and the score after each
+    // removal.
+    pub fn prune_to_bytes(
+        &mut self,
+        max_bytes: usize,
+        dims: (u32, u32),
+        precision: usize,
+        minify: bool,
+    ) -> Vec<(usize, f32)> {
+        let (w, h) = dims;
+        let scale = w.max(h) as f32 / self.w.max(self.h) as f32;
+        let mut levels = Vec::new();
+        loop {
+            let commands: Vec<DrawCommand> =
+                self.commands.iter().map(|cmd| cmd.scaled(scale)).collect();
+            let size = svg_string(w, h, &commands, &self.bg_color, precision, minify).len();
+            if let Some(last) = levels.last_mut() {
+                *last = (size, self.score);
+            }
+            if size <= max_bytes || self.commands.is_empty() {
+                return levels;
+            }
+            // sizes add up while the commands keep sharing their alpha or
+            // not, a removal that makes it shared only shrinks the output
+            let alpha = shared_alpha(&self.commands);
+            let weight =
+                |cmd: &DrawCommand| command_size(&cmd.scaled(scale), alpha, precision, minify);
+            let total: usize = self.commands.iter().map(weight).sum();
+            let base = size - total;
+            for (bytes, score) in self.prune_by(weight, total.saturating_sub(size - max_bytes)) {
+                levels.push((base + bytes, score));
+            }
+        }
+    }
+
+    // removes the command with the lowest score cost per `weight` while the
+    // summed weight of the commands is over `limit`, returns the summed
+    // weight and the score after each removal
+    fn prune_by<F: Fn(&DrawCommand) -> usize>(
+        &mut self,
+        weight: F,
+        limit: usize,
+    ) -> Vec<(usize, f32)> {
+        let mut levels = Vec::new();
+        let mut layers: Vec<Layer> = self.commands.iter().map(|&cmd| self.layer(cmd)).collect();
+        let mut work = (*self.canvas).clone();
+        let mut total: usize = layers.iter().map(|layer| weight(&layer.cmd)).sum();
+
+        // score change from removing each layer, kept until a removal nearby
+        let mut deltas: Vec<Option<f32>> = vec![None; layers.len()];
+        while total > limit && !layers.is_empty() {
+            for j in 0..layers.len() {
+                if deltas[j].is_some() {
+                    continue;
+                }
+                let delta = match layers[j].rect {
+                    Some(rect) => {
+                        let score = self.score_layers(&mut work, &layers, Some(j), rect);
+                        copy_region(&self.canvas, &mut work, rect);
+                        score - self.score
+                    }
+                    None => 0.0,
+                };
+                deltas[j] = Some(delta);
+            }
+
+            let cost = |j: usize| deltas[j].unwrap() / weight(&layers[j].cmd) as f32;
+            let mut best = 0;
+            for j in 1..layers.len() {
+                if cost(j) < cost(best) {
+                    best = j;
+                }
+            }
+
+            if let Some(rect) = layers[best].rect {
+                self.score_layers(&mut work, &layers, Some(best), rect);
+                self.commit_region(&work, rect);
+
+                // removal scores depend on the ssim blocks around a layer
+                let (x0, y0, x1, y1) = rect;
+                let area = (
+                    x0.saturating_sub(BLOCK_SIZE),
+                    y0.saturating_sub(BLOCK_SIZE),
+                    x1 + BLOCK_SIZE,
+                    y1 + BLOCK_SIZE,
+                );
+                for (j, layer) in layers.iter().enumerate() {
+                    if layer.rect.and_then(|r| intersect_rect(r, area)).is_some() {
+                        deltas[j] = None;
+                    }
+                }
+            }
+            total -= weight(&layers[best].cmd);
+            layers.remove(best);
+            deltas.remove(best);
+            levels.push((total, self.score));
+        }
+
+        self.commands = layers.iter().map(|layer| layer.cmd).collect();
+        levels
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WIDTH: u32 = 24;
+    const HEIGHT: u32 = 16;
+
+    // a model of a WIDTH x HEIGHT gradient
+    fn test_model(optimizer: Optimizer) -> Model {
+        let mut target = Vec::new();
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                target.extend_from_slice(&[(10 * x) as u8, (15 * y) as u8, 90, 255]);
+            }
+        }
+        let bg_color = avg_color(WIDTH, HEIGHT, &target);
+        Model::new(WIDTH, HEIGHT, target, bg_color, optimizer)
+    }
+
+    #[test]
+    fn model_step_test() {
+        let mut model = test_model(Optimizer {
+            num: 8,
+            seed_count: 4,
+            optimize_count: 8,
+            ..Optimizer::default()
+        });
+        let initial_score = model.score();
+
+        model.run(8);
+
+        assert_eq!(model.iteration(), 8);
+        assert!(model.commands().len() <= 8);
+        assert!(model.score() <= initial_score);
+    }
+
+    #[test]
+    fn model_solve_alpha_test() {
+        // the default bounds leave alpha to the solver
+        let mut model = test_model(Optimizer {
+            num: 8,
+            seed_count: 4,
+            optimize_count: 8,
+            color_mode: ColorMode::SolveAlpha,
+            ..Optimizer::default()
+        });
+        model.run(8);
+
+        assert!(!model.commands().is_empty());
+        assert!(model.commands().iter().any(|cmd| cmd.color.alpha() != 128));
+    }
+
+    #[test]
+    fn model_seed_test() {
+        let optimizer = Optimizer {
+            num: 8,
+            seed_count: 6,
+            optimize_count: 8,
+            shape: ShapeType::Any,
+            seed: 42,
+            placement: Placement::Error,
+            orient: true,
+            ..Optimizer::default()
+        };
+
+        let mut commands = Vec::new();
+        for &threads in [1, 4].iter() {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap();
+            let mut model = test_model(optimizer.clone());
+            pool.install(|| model.run(8));
+            assert!(!model.commands().is_empty());
+            commands.push(model.commands().to_vec());
+        }
+        assert_eq!(commands[0], commands[1]);
+    }
+
+    #[test]
+    fn refine_rng_test() {
+        // a refine pass does not replay the candidates of the next iteration
+        let mut candidate = candidate_rng(7, 10, 3);
+        let mut refine = refine_rng(7, 10, 3);
+        assert_ne!(candidate.gen::<u64>(), refine.gen::<u64>());
+        assert_eq!(
+            candidate_rng(7, 10, 3).gen::<u64>(),
+            candidate_rng(7, 10, 3).gen::<u64>()
+        );
+    }
+
+    #[test]
+    fn accept_test() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let anneal = Optimizer {
+            strategy: Strategy::Anneal,
+            ..Optimizer::default()
+        };
+        let hill = Optimizer::default();
+        for optimizer in [&anneal, &hill].iter() {
+            assert!(optimizer.accept(0.5, 0.6, 0.0, &mut rng));
+            assert!(optimizer.accept(0.5, 0.6, 1.0, &mut rng));
+        }
+
+        // a score worse by 0.1 at temperature 0.1 passes with p = 1/e
+        let count = |optimizer: &Optimizer, temperature: f32, rng: &mut StdRng| {
+            (0..1000)
+                .filter(|_| optimizer.accept(0.6, 0.5, temperature, rng))
+                .count()
+        };
+        let accepted = count(&anneal, 0.1, &mut rng);
+        assert!((300..440).contains(&accepted), "{}", accepted);
+        assert_eq!(count(&anneal, 0.0, &mut rng), 0);
+        assert_eq!(count(&hill, 0.1, &mut rng), 0);
+        assert_eq!(count(&hill, 1e9, &mut rng), 0);
+    }
+
+    #[test]
+    fn model_anneal_test() {
+        let optimizer = Optimizer {
+            num: 8,
+            seed_count: 4,
+            optimize_count: 16,
+            seed: 7,
+            strategy: Strategy::Anneal,
+            // hot enough that worse mutations get accepted
+            temperature: 1.0,
+            ..Optimizer::default()
+        };
+        let mut model = test_model(optimizer.clone());
+        let initial_score = model.score();
+        model.run(8);
+        // the best candidate is kept, not the last one
+        assert!(model.score() < initial_score);
+
+        // accepted worse moves take the search elsewhere than hill climbing
+        let hill = Optimizer {
+            strategy: Strategy::Hill,
+            ..optimizer
+        };
+        let mut hill_model = test_model(hill);
+        hill_model.run(8);
+        assert_ne!(model.commands(), hill_model.commands());
+        assert_eq!("anneal".parse::<Strategy>(), Ok(Strategy::Anneal));
+        assert!("greedy".parse::<Strategy>().is_err());
+    }
+
+    #[test]
+    fn model_resume_test() {
+        let mut model = test_model(Optimizer {
+            num: 8,
+            seed_count: 4,
+            optimize_count: 8,
+            shape: ShapeType::Any,
+            seed: 7,
+            // scored the same after resuming
+            auto_weight: Some(AutoWeight::Variance),
+            ..Optimizer::default()
+        });
+        model.run(4);
+        let checkpoint: Checkpoint = model.checkpoint().to_string().parse().unwrap();
+        model.run(4);
+
+        let mut resumed = Model::from_checkpoint(model.target().to_vec(), &checkpoint);
+        assert_eq!(resumed.iteration(), 4);
+        // clearing explicit weights keeps the automatic ones
+        let score = resumed.score();
+        resumed.set_weights(None);
+        assert_eq!(resumed.score(), score);
+        resumed.run(4);
+
+        assert_eq!(resumed.commands(), model.commands());
+        assert_eq!(resumed.score(), model.score());
+        assert_eq!(resumed.canvas().data(), model.canvas().data());
+
+        // carried over to a target twice the size
+        let mut large_target = Vec::new();
+        for y in 0..2 * HEIGHT {
+            for x in 0..2 * WIDTH {
+                large_target.extend_from_slice(&[(5 * x) as u8, (7 * y) as u8, 90, 255]);
+            }
+        }
+        let mut large = model.resized(2 * WIDTH, 2 * HEIGHT, large_target);
+        assert_eq!(large.iteration(), model.iteration());
+        assert_eq!(large.commands()[0], model.commands()[0].scaled(2.0));
+        large.run(1);
+        assert_eq!(large.iteration(), model.iteration() + 1);
+    }
+
+    #[test]
+    fn model_refine_color_test() {
+        // a fixed alpha, only the color is solved
+        let mut model = test_model(Optimizer {
+            num: 10,
+            alpha_min: 128,
+            alpha_max: 128,
+            seed_count: 4,
+            optimize_count: 4,
+            seed: 6,
+            ..Optimizer::default()
+        });
+        model.run(10);
+        let before = model.commands().to_vec();
+        let refinement = model.refine(8, false);
+        assert!(refinement.improved > 0);
+        assert_eq!(refinement.reordered, 0);
+
+        // a moved command gets the color solved over the commands below it,
+        // with the alpha it was placed with
+        let target = model.target();
+        let mut canvas = Pixmap::new(WIDTH, HEIGHT).unwrap();
+        draw_bg(&mut canvas, &model.bg_color());
+        for (cmd, old) in model.commands().iter().zip(before.iter()) {
+            let coverage = Coverage::rasterize(&cmd.shape, WIDTH, HEIGHT);
+            assert_eq!(cmd.color.alpha(), old.color.alpha());
+            if cmd.shape != old.shape {
+                let color = solve_color(target, &canvas, &coverage, cmd.color.alpha());
+                assert_eq!(cmd.color, color);
+            }
+            fill_coverage(&mut canvas, &coverage, &cmd.color);
+        }
+    }
+
+    #[test]
+    fn model_refine_prune_test() {
+        let mut model = test_model(Optimizer {
+            num: 12,
+            seed_count: 4,
+            optimize_count: 4,
+            shape: ShapeType::Any,
+            seed: 11,
+            ..Optimizer::default()
+        });
+        model.run(12);
+        let count = model.commands().len();
+        let score = model.score();
+
+        let refinement = model.refine(8, true);
+        assert!(model.score() <= score);
+        assert_eq!(model.commands().len(), count - refinement.removed);
+
+        // the canvas still is the command list drawn in order
+        let target = model.target().to_vec();
+        let rebuilt = Model::from_checkpoint(target.clone(), &model.checkpoint());
+        assert_eq!(rebuilt.canvas().data(), model.canvas().data());
+        assert!((rebuilt.score() - model.score()).abs() < 1e-6);
+
+        let count = model.commands().len();
+        let levels = model.prune(count - 3);
+        assert_eq!(model.commands().len(), count - 3);
+        assert_eq!(levels.len(), 3);
+        assert_eq!(levels[2], (count - 3, model.score()));
+        let rebuilt = Model::from_checkpoint(target, &model.checkpoint());
+        assert_eq!(rebuilt.canvas().data(), model.canvas().data());
+
+        let bg_color = model.bg_color();
+        let size =
+            |model: &Model| svg_string(WIDTH, HEIGHT, model.commands(), &bg_color, 2, true).len();
+        let max_bytes = size(&model) - 150;
+        let levels = model.prune_to_bytes(max_bytes, (WIDTH, HEIGHT), 2, true);
+        assert!(size(&model) <= max_bytes);
+        assert_eq!(levels.last().unwrap(), &(size(&model), model.score()));
+    }
+}
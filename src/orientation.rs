@@ -0,0 +1,104 @@
+use lab::Lab;
+
+use crate::importance::{box_blur, channels, sobel};
+
+// direction of the local structure of the target, from the structure tensor
+// of its Lab channels
+#[derive(Clone, Debug, PartialEq)]
+pub struct Orientation {
+    w: u32,
+    h: u32,
+    // degree, along the isophotes
+    angles: Vec<f32>,
+    // 0.0 (flat or isotropic) - 1.0 (a single straight edge)
+    coherences: Vec<f32>,
+}
+
+impl Orientation {
+    pub fn new(lab_img: &[Lab], w: u32, h: u32) -> Orientation {
+        assert_eq!(lab_img.len(), (w * h) as usize);
+        let n = (w * h) as usize;
+        let mut jxx = vec![0.0; n];
+        let mut jxy = vec![0.0; n];
+        let mut jyy = vec![0.0; n];
+        for channel in channels(lab_img).iter() {
+            for (i, (gx, gy)) in sobel(channel, w, h).into_iter().enumerate() {
+                jxx[i] += gx * gx;
+                jxy[i] += gx * gy;
+                jyy[i] += gy * gy;
+            }
+        }
+        // the tensor is averaged over about 1/64 of the image, like the
+        // automatic weights
+        let r = (w.max(h) / 64).max(1);
+        let jxx = box_blur(&jxx, w, h, r);
+        let jxy = box_blur(&jxy, w, h, r);
+        let jyy = box_blur(&jyy, w, h, r);
+
+        let mut angles = Vec::with_capacity(n);
+        let mut coherences = Vec::with_capacity(n);
+        for i in 0..n {
+            let trace = jxx[i] + jyy[i];
+            let diff = jxx[i] - jyy[i];
+            // eigenvalue difference over their sum
+            let coherence = if trace > 0.0 {
+                ((diff * diff + 4.0 * jxy[i] * jxy[i]).sqrt() / trace).min(1.0)
+            } else {
+                0.0
+            };
+            // the gradient direction, turned by 90 degree
+            let gradient = 0.5 * (2.0 * jxy[i]).atan2(diff);
+            angles.push((gradient.to_degrees() + 90.0).rem_euclid(180.0));
+            coherences.push(coherence);
+        }
+        Orientation {
+            w,
+            h,
+            angles,
+            coherences,
+        }
+    }
+
+    // (angle, coherence) at pixel (x, y)
+    pub fn at(&self, x: u32, y: u32) -> (f32, f32) {
+        assert!(x < self.w && y < self.h);
+        let i = (x + self.w * y) as usize;
+        (self.angles[i], self.coherences[i])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn orientation_test() {
+        const SIZE: u32 = 32;
+
+        let stripes = |horizontal: bool| {
+            let mut lab_img = Vec::new();
+            for y in 0..SIZE {
+                for x in 0..SIZE {
+                    let v = if horizontal { y } else { x };
+                    let rgb = if (v / 4) % 2 == 0 {
+                        [0, 0, 0]
+                    } else {
+                        [255; 3]
+                    };
+                    lab_img.push(Lab::from_rgb(&rgb));
+                }
+            }
+            Orientation::new(&lab_img, SIZE, SIZE)
+        };
+
+        // the structure runs along the stripes
+        let (angle, coherence) = stripes(true).at(16, 16);
+        assert!(angle.abs() < 1e-3 || (angle - 180.0).abs() < 1e-3);
+        assert!(coherence > 0.99);
+        let (angle, coherence) = stripes(false).at(16, 16);
+        assert!((angle - 90.0).abs() < 1e-3);
+        assert!(coherence > 0.99);
+
+        let flat = vec![Lab::from_rgb(&[10, 20, 30]); (SIZE * SIZE) as usize];
+        assert_eq!(Orientation::new(&flat, SIZE, SIZE).at(3, 5).1, 0.0);
+    }
+}
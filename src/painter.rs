@@ -0,0 +1,471 @@
+use std::fs;
+
+use image::imageops::FilterType;
+use image::{GrayImage, RgbaImage};
+use tiny_skia::*;
+
+use crate::checkpoint::Checkpoint;
+use crate::model::{render, Model, Optimizer, Refinement, Step};
+use crate::DrawCommand;
+
+// size fitting the longer side in `max_size`, never larger than (w, h)
+pub fn work_dims(w: u32, h: u32, max_size: u32) -> (u32, u32) {
+    let size = w.max(h);
+    if max_size >= size {
+        return (w, h);
+    }
+    let ratio = max_size as f32 / size as f32;
+    let scale = |v: u32| ((v as f32 * ratio).round() as u32).max(1);
+    (scale(w), scale(h))
+}
+
+// the sizes a (w, h) image is worked on, smallest first: `pyramid` levels of
+// doubling size up to `work_size`, or up to the input size without one
+pub fn levels(w: u32, h: u32, work_size: Option<u32>, pyramid: u32) -> Vec<(u32, u32)> {
+    let work_size = work_size.unwrap_or(w.max(h));
+    (0..pyramid)
+        .rev()
+        .map(|k| work_dims(w, h, (work_size >> k).max(1)))
+        .collect()
+}
+
+// the rgba target of a level
+pub fn resize_target(image: &RgbaImage, dims: (u32, u32)) -> Vec<u8> {
+    if dims == image.dimensions() {
+        return image.as_raw().clone();
+    }
+    image::imageops::resize(image, dims.0, dims.1, FilterType::Lanczos3).into_raw()
+}
+
+pub fn absolute_path(path: &str) -> Result<String, String> {
+    let path = fs::canonicalize(path).map_err(|e| format!("{}: {}", path, e))?;
+    Ok(path.to_string_lossy().into_owned())
+}
+
+fn levels_of(image: &RgbaImage, options: &PainterOptions) -> Result<Vec<(u32, u32)>, String> {
+    if options.pyramid == 0 {
+        return Err("pyramid must be at least 1".to_string());
+    }
+    if options.work_size == Some(0) {
+        return Err("work size must be at least 1".to_string());
+    }
+    let (w, h) = image.dimensions();
+    Ok(levels(w, h, options.work_size, options.pyramid))
+}
+
+// grayscale importance of each pixel of a (w, h) image, white counts fully
+fn load_mask(path: &str, w: u32, h: u32) -> Result<GrayImage, String> {
+    let mask = image::open(path)
+        .map_err(|e| format!("{}: {}", path, e))?
+        .to_luma8();
+    if mask.dimensions() != (w, h) {
+        return Err(format!("{} is not {}x{}", path, w, h));
+    }
+    Ok(mask)
+}
+
+// what a `Painter` adds to the optimizer of its model
+#[derive(Clone, Debug, PartialEq)]
+pub struct PainterOptions {
+    // longer side of the largest target the model works on, the input size
+    // if None
+    pub work_size: Option<u32>,
+    // levels of doubling size the iterations are split between
+    pub pyramid: u32,
+    // grayscale image of the input size, white pixels count fully in the
+    // score
+    pub weight_mask: Option<String>,
+}
+
+impl Default for PainterOptions {
+    fn default() -> PainterOptions {
+        PainterOptions {
+            work_size: None,
+            pyramid: 1,
+            weight_mask: None,
+        }
+    }
+}
+
+// Runs a `Model` on the work size and pyramid levels of its options. The
+// iterations are split evenly between the levels, so the large early shapes
+// are placed on a small target. Commands and canvas are at the input size.
+pub struct Painter {
+    image: RgbaImage,
+    options: PainterOptions,
+    mask: Option<GrayImage>,
+    levels: Vec<(u32, u32)>,
+    model: Model,
+    // the commands drawn at the input size, when the model works smaller
+    canvas: Option<Pixmap>,
+}
+
+impl Painter {
+    pub fn new(
+        image: RgbaImage,
+        bg_color: ColorU8,
+        optimizer: Optimizer,
+        mut options: PainterOptions,
+    ) -> Result<Painter, String> {
+        // kept absolute so the checkpoint resumes from any directory
+        if let Some(ref path) = options.weight_mask {
+            options.weight_mask = Some(absolute_path(path)?);
+        }
+        let levels = levels_of(&image, &options)?;
+        let (work_w, work_h) = levels[0];
+        let target = resize_target(&image, levels[0]);
+        let model = Model::new(work_w, work_h, target, bg_color, optimizer);
+        Painter::with_model(image, options, levels, model)
+    }
+
+    // continues a run on the same input image
+    pub fn from_checkpoint(image: RgbaImage, checkpoint: &Checkpoint) -> Result<Painter, String> {
+        let options = checkpoint.painter.clone();
+        let levels = levels_of(&image, &options)?;
+        let dims = (checkpoint.w, checkpoint.h);
+        if !levels.contains(&dims) {
+            let (w, h) = image.dimensions();
+            return Err(format!("not a checkpoint of a {}x{} image", w, h));
+        }
+        let model = Model::from_checkpoint(resize_target(&image, dims), checkpoint);
+        Painter::with_model(image, options, levels, model)
+    }
+
+    fn with_model(
+        image: RgbaImage,
+        options: PainterOptions,
+        levels: Vec<(u32, u32)>,
+        model: Model,
+    ) -> Result<Painter, String> {
+        let (w, h) = image.dimensions();
+        let mask = match options.weight_mask {
+            Some(ref path) => Some(load_mask(path, w, h)?),
+            None => None,
+        };
+        let canvas = if levels.iter().any(|&dims| dims != (w, h)) {
+            Some(Pixmap::new(w, h).unwrap())
+        } else {
+            None
+        };
+        let mut painter = Painter {
+            image,
+            options,
+            mask,
+            levels,
+            model,
+            canvas,
+        };
+        painter.apply_mask();
+        painter.redraw();
+        Ok(painter)
+    }
+
+    fn apply_mask(&mut self) {
+        let mask = match self.mask {
+            Some(ref mask) => mask,
+            None => return,
+        };
+        let dims = (self.model.width(), self.model.height());
+        let weights = if dims == mask.dimensions() {
+            mask.pixels().map(|p| p[0] as f32 / 255.0).collect()
+        } else {
+            let mask = image::imageops::resize(mask, dims.0, dims.1, FilterType::Triangle);
+            mask.pixels().map(|p| p[0] as f32 / 255.0).collect()
+        };
+        self.model.set_weights(Some(weights));
+    }
+
+    // renders the input-size canvas again after commands were changed
+    fn redraw(&mut self) {
+        if self.canvas.is_some() {
+            let (w, h) = self.image.dimensions();
+            self.canvas = Some(render(w, h, &self.model.bg_color(), &self.commands()));
+        }
+    }
+
+    fn set_level(&mut self, dims: (u32, u32)) {
+        let target = resize_target(&self.image, dims);
+        self.model = self.model.resized(dims.0, dims.1, target);
+        self.apply_mask();
+    }
+
+    fn level_at(&self, iteration: u32) -> (u32, u32) {
+        let num = self.model.optimizer().num;
+        let level = iteration as u64 * self.options.pyramid as u64 / num.max(1) as u64;
+        self.levels[(level as usize).min(self.levels.len() - 1)]
+    }
+
+    // draws `commands` of the input size as if they had been committed
+    pub fn push_commands(&mut self, commands: &[DrawCommand]) {
+        let scale = 1.0 / self.scale();
+        let commands: Vec<DrawCommand> = commands.iter().map(|cmd| cmd.scaled(scale)).collect();
+        self.model.push_commands(&commands);
+        self.redraw();
+    }
+
+    // `Model::step` on the level of the current iteration
+    pub fn step(&mut self) -> Step {
+        let dims = self.level_at(self.model.iteration());
+        let level = self.level();
+        // never back to a smaller level, as after `num` was raised on resume
+        if dims.0 * dims.1 > level.0 * level.1 {
+            self.set_level(dims);
+        }
+        let step = self.model.step();
+        let scale = self.scale();
+        if let (Some(cmd), Some(canvas)) = (step.command, self.canvas.as_mut()) {
+            cmd.scaled(scale).draw(canvas, true);
+        }
+        step
+    }
+
+    pub fn run(&mut self, n: u32) {
+        for _ in 0..n {
+            self.step();
+        }
+    }
+
+    // moves to the last level, a run can stop before it gets there
+    pub fn finish_levels(&mut self) {
+        let dims = *self.levels.last().unwrap();
+        if dims != self.level() {
+            self.set_level(dims);
+        }
+    }
+
+    pub fn prune(&mut self, count: usize) -> Vec<(usize, f32)> {
+        let levels = self.model.prune(count);
+        self.redraw();
+        levels
+    }
+
+    pub fn refine(&mut self, mutate_count: u32, remove: bool) -> Refinement {
+        let refinement = self.model.refine(mutate_count, remove);
+        self.redraw();
+        refinement
+    }
+
+    // `Model::prune_to_bytes` for the svg of the input size
+    pub fn prune_to_bytes(
+        &mut self,
+        max_bytes: usize,
+        precision: usize,
+        minify: bool,
+    ) -> Vec<(usize, f32)> {
+        let dims = (self.width(), self.height());
+        let levels = self
+            .model
+            .prune_to_bytes(max_bytes, dims, precision, minify);
+        self.redraw();
+        levels
+    }
+
+    pub fn width(&self) -> u32 {
+        self.image.width()
+    }
+
+    pub fn height(&self) -> u32 {
+        self.image.height()
+    }
+
+    pub fn levels(&self) -> &[(u32, u32)] {
+        &self.levels
+    }
+
+    // the size the model works on now
+    pub fn level(&self) -> (u32, u32) {
+        (self.model.width(), self.model.height())
+    }
+
+    // from the work size to the input size
+    pub fn scale(&self) -> f32 {
+        self.width().max(self.height()) as f32 / self.model.width().max(self.model.height()) as f32
+    }
+
+    // the commands at the input size
+    pub fn commands(&self) -> Vec<DrawCommand> {
+        let scale = self.scale();
+        self.model
+            .commands()
+            .iter()
+            .map(|cmd| cmd.scaled(scale))
+            .collect()
+    }
+
+    // the commands drawn at the input size
+    pub fn canvas(&self) -> &Pixmap {
+        match self.canvas {
+            Some(ref canvas) => canvas,
+            None => self.model.canvas(),
+        }
+    }
+
+    pub fn model(&self) -> &Model {
+        &self.model
+    }
+
+    // e.g. to set a palette, changing the commands leaves `canvas` stale
+    pub fn model_mut(&mut self) -> &mut Model {
+        &mut self.model
+    }
+
+    pub fn options(&self) -> &PainterOptions {
+        &self.options
+    }
+
+    pub fn checkpoint(&self) -> Checkpoint {
+        let mut checkpoint = self.model.checkpoint();
+        checkpoint.painter = self.options.clone();
+        checkpoint
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::avg_color;
+    use crate::svg::svg_string;
+
+    #[test]
+    fn painter_test() {
+        const WIDTH: u32 = 32;
+        const HEIGHT: u32 = 24;
+
+        assert_eq!(work_dims(WIDTH, HEIGHT, 16), (16, 12));
+        assert_eq!(work_dims(WIDTH, HEIGHT, 64), (WIDTH, HEIGHT));
+        assert_eq!(levels(WIDTH, HEIGHT, None, 2), vec![(16, 12), (32, 24)]);
+
+        let image = RgbaImage::from_fn(WIDTH, HEIGHT, |x, y| {
+            image::Rgba([(8 * x) as u8, (10 * y) as u8, 100, 255])
+        });
+        let optimizer = Optimizer {
+            num: 4,
+            seed_count: 4,
+            optimize_count: 4,
+            seed: 3,
+            ..Optimizer::default()
+        };
+        let options = PainterOptions {
+            work_size: Some(16),
+            pyramid: 2,
+            weight_mask: None,
+        };
+        let bg_color = avg_color(WIDTH, HEIGHT, image.as_raw());
+        let zero = PainterOptions {
+            work_size: Some(0),
+            ..options.clone()
+        };
+        assert!(Painter::new(image.clone(), bg_color, optimizer.clone(), zero).is_err());
+        let mut painter = Painter::new(image, bg_color, optimizer, options).unwrap();
+        assert_eq!(painter.levels(), &[(8, 6), (16, 12)]);
+        assert_eq!(painter.level(), (8, 6));
+
+        // half of the iterations on each level
+        painter.run(2);
+        assert_eq!(painter.level(), (8, 6));
+        painter.step();
+        assert_eq!(painter.level(), (16, 12));
+        painter.run(1);
+        assert!(!painter.commands().is_empty());
+
+        // the canvas is the input-size render of the commands
+        assert_eq!(painter.scale(), 2.0);
+        assert_eq!(
+            painter.commands()[0],
+            painter.model().commands()[0].scaled(2.0)
+        );
+        let canvas = render(WIDTH, HEIGHT, &bg_color, &painter.commands());
+        assert_eq!(painter.canvas().data(), canvas.data());
+        painter.refine(2, true);
+        let canvas = render(WIDTH, HEIGHT, &bg_color, &painter.commands());
+        assert_eq!(painter.canvas().data(), canvas.data());
+    }
+
+    #[test]
+    fn painter_resume_test() {
+        const WIDTH: u32 = 32;
+        const HEIGHT: u32 = 24;
+
+        let image = RgbaImage::from_fn(WIDTH, HEIGHT, |x, y| {
+            image::Rgba([(8 * x) as u8, (10 * y) as u8, 100, 255])
+        });
+        let optimizer = Optimizer {
+            num: 6,
+            seed_count: 4,
+            optimize_count: 4,
+            seed: 5,
+            ..Optimizer::default()
+        };
+        let options = PainterOptions {
+            work_size: Some(16),
+            pyramid: 3,
+            weight_mask: None,
+        };
+        let bg_color = avg_color(WIDTH, HEIGHT, image.as_raw());
+        let mut painter = Painter::new(image.clone(), bg_color, optimizer, options).unwrap();
+        assert_eq!(painter.levels(), &[(4, 3), (8, 6), (16, 12)]);
+
+        // stopped on the middle level
+        painter.run(3);
+        assert_eq!(painter.level(), (8, 6));
+        let text = painter.checkpoint().to_string();
+
+        painter.run(3);
+        assert_eq!(painter.level(), (16, 12));
+
+        let checkpoint: Checkpoint = text.parse().unwrap();
+        let mut resumed = Painter::from_checkpoint(image, &checkpoint).unwrap();
+        assert_eq!(resumed.options(), painter.options());
+        assert_eq!(resumed.levels(), painter.levels());
+        assert_eq!(resumed.level(), (8, 6));
+        resumed.run(3);
+        assert_eq!(resumed.level(), (16, 12));
+        assert_eq!(resumed.commands(), painter.commands());
+        assert_eq!(resumed.model().score(), painter.model().score());
+        assert_eq!(resumed.canvas().data(), painter.canvas().data());
+
+        // a longer run stays on its level until the schedule catches up
+        let mut longer = checkpoint.clone();
+        longer.optimizer.num = 12;
+        let mut resumed = Painter::from_checkpoint(painter.image.clone(), &longer).unwrap();
+        resumed.run(5);
+        assert_eq!(resumed.level(), (8, 6));
+        resumed.step();
+        assert_eq!(resumed.level(), (16, 12));
+    }
+
+    #[test]
+    fn painter_prune_test() {
+        // a 16x16 model scaled back up rounds to 100x100, not 100x97
+        const WIDTH: u32 = 100;
+        const HEIGHT: u32 = 97;
+
+        let image = RgbaImage::from_fn(WIDTH, HEIGHT, |x, y| {
+            image::Rgba([(2 * x) as u8, (2 * y) as u8, 100, 255])
+        });
+        let optimizer = Optimizer {
+            num: 8,
+            seed_count: 4,
+            optimize_count: 4,
+            seed: 3,
+            ..Optimizer::default()
+        };
+        let options = PainterOptions {
+            work_size: Some(16),
+            ..PainterOptions::default()
+        };
+        let bg_color = avg_color(WIDTH, HEIGHT, image.as_raw());
+        let mut painter = Painter::new(image, bg_color, optimizer, options).unwrap();
+        painter.run(8);
+        assert_eq!(painter.level(), (16, 16));
+
+        let size = |painter: &Painter| {
+            let bg = painter.model().bg_color();
+            svg_string(WIDTH, HEIGHT, &painter.commands(), &bg, 2, true).len()
+        };
+        let max_bytes = size(&painter) - 1;
+        let levels = painter.prune_to_bytes(max_bytes, 2, true);
+        assert!(size(&painter) <= max_bytes);
+        assert_eq!(levels.last().unwrap().0, size(&painter));
+    }
+}
@@ -0,0 +1,254 @@
+use std::fmt;
+use std::fs;
+use std::str::FromStr;
+
+use lab::Lab;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use tiny_skia::*;
+
+use crate::shape::rnd;
+use crate::ColorConverter;
+
+const KMEANS_ITERATIONS: u32 = 32;
+
+// fixed set of colors the commands are drawn with, matched in the Lab of
+// `ColorConverter` like the scoring
+#[derive(Clone, Debug, PartialEq)]
+pub struct Palette {
+    colors: Vec<[u8; 3]>,
+}
+
+fn nearest_index(labs: &[Lab], lab: &Lab) -> usize {
+    let mut best = 0;
+    let mut best_distance = f32::MAX;
+    for (i, other) in labs.iter().enumerate() {
+        let distance = lab.squared_distance(other);
+        if distance < best_distance {
+            best = i;
+            best_distance = distance;
+        }
+    }
+    best
+}
+
+// "#rrggbb", "rrggbb" or "#rgb"
+fn parse_hex(s: &str) -> Result<[u8; 3], String> {
+    let hex = s.strip_prefix('#').unwrap_or(s);
+    let digits = match hex.len() {
+        6 => 2,
+        3 => 1,
+        _ => return Err(format!("invalid color: {}", s)),
+    };
+    let mut rgb = [0; 3];
+    for (i, c) in rgb.iter_mut().enumerate() {
+        let v = u8::from_str_radix(&hex[digits * i..digits * (i + 1)], 16)
+            .map_err(|_| format!("invalid color: {}", s))?;
+        *c = if digits == 1 { v * 17 } else { v };
+    }
+    Ok(rgb)
+}
+
+impl Palette {
+    pub fn new(colors: Vec<[u8; 3]>) -> Palette {
+        assert!(!colors.is_empty());
+        Palette { colors }
+    }
+
+    // the 6x6x6 web-safe colors
+    pub fn web216() -> Palette {
+        let mut colors = Vec::new();
+        for r in 0..6 {
+            for g in 0..6 {
+                for b in 0..6 {
+                    colors.push([51 * r, 51 * g, 51 * b]);
+                }
+            }
+        }
+        Palette::new(colors)
+    }
+
+    // `count` colors clustering the pixels of the rgba `target` with k-means,
+    // started by k-means++ from `seed`. Each color is the pixel nearest to its
+    // cluster's mean.
+    pub fn kmeans(converter: &ColorConverter, target: &[u8], count: usize, seed: u64) -> Palette {
+        let lab_img: Vec<Lab> = target
+            .chunks(4)
+            .map(|p| converter.get_lab(p[0], p[1], p[2]))
+            .collect();
+        assert!(count > 0 && !lab_img.is_empty());
+        let mut rng = StdRng::seed_from_u64(seed);
+        let n = lab_img.len();
+
+        let mut centers = vec![lab_img[rnd(&mut rng, 0, n - 1)]];
+        let mut distances: Vec<f32> = lab_img
+            .iter()
+            .map(|lab| lab.squared_distance(&centers[0]))
+            .collect();
+        while centers.len() < count {
+            let total: f32 = distances.iter().sum();
+            // fewer distinct colors than `count`
+            if total <= 0.0 {
+                break;
+            }
+            let mut target = rnd(&mut rng, 0.0, total);
+            let mut index = n - 1;
+            for (i, &d) in distances.iter().enumerate() {
+                if target < d {
+                    index = i;
+                    break;
+                }
+                target -= d;
+            }
+            let center = lab_img[index];
+            centers.push(center);
+            for (d, lab) in distances.iter_mut().zip(lab_img.iter()) {
+                *d = d.min(lab.squared_distance(&center));
+            }
+        }
+
+        for _ in 0..KMEANS_ITERATIONS {
+            let mut sums = vec![([0.0_f64; 3], 0_usize); centers.len()];
+            for lab in lab_img.iter() {
+                let (sum, n) = &mut sums[nearest_index(&centers, lab)];
+                sum[0] += lab.l as f64;
+                sum[1] += lab.a as f64;
+                sum[2] += lab.b as f64;
+                *n += 1;
+            }
+            let mut moved = false;
+            for (center, (sum, n)) in centers.iter_mut().zip(sums.iter()) {
+                if *n == 0 {
+                    continue;
+                }
+                let n = *n as f64;
+                let mean = Lab {
+                    l: (sum[0] / n) as f32,
+                    a: (sum[1] / n) as f32,
+                    b: (sum[2] / n) as f32,
+                };
+                moved |= mean != *center;
+                *center = mean;
+            }
+            if !moved {
+                break;
+            }
+        }
+
+        let mut nearest: Vec<Option<(f32, [u8; 3])>> = vec![None; centers.len()];
+        for (lab, p) in lab_img.iter().zip(target.chunks(4)) {
+            let i = nearest_index(&centers, lab);
+            let distance = lab.squared_distance(&centers[i]);
+            let closer = match nearest[i] {
+                Some((d, _)) => distance < d,
+                None => true,
+            };
+            if closer {
+                nearest[i] = Some((distance, [p[0], p[1], p[2]]));
+            }
+        }
+        Palette::new(nearest.iter().flatten().map(|&(_, rgb)| rgb).collect())
+    }
+
+    pub fn load(path: &str) -> Result<Palette, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        text.parse().map_err(|e| format!("{}: {}", path, e))
+    }
+
+    pub fn colors(&self) -> &[[u8; 3]] {
+        &self.colors
+    }
+
+    // nearest palette color in Lab, keeping the alpha
+    pub fn snap(&self, converter: &ColorConverter, color: &ColorU8) -> ColorU8 {
+        let lab = converter.get_lab(color.red(), color.green(), color.blue());
+        let mut best = 0;
+        let mut best_distance = f32::MAX;
+        for (i, &[r, g, b]) in self.colors.iter().enumerate() {
+            let distance = lab.squared_distance(&converter.get_lab(r, g, b));
+            if distance < best_distance {
+                best = i;
+                best_distance = distance;
+            }
+        }
+        let [r, g, b] = self.colors[best];
+        ColorU8::from_rgba(r, g, b, color.alpha())
+    }
+
+    pub fn random(&self, rng: &mut StdRng, alpha: u8) -> ColorU8 {
+        let [r, g, b] = self.colors[rnd(rng, 0, self.colors.len() - 1)];
+        ColorU8::from_rgba(r, g, b, alpha)
+    }
+}
+
+// hex colors separated by spaces, commas or newlines
+impl FromStr for Palette {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Palette, String> {
+        let colors = s
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|v| !v.is_empty())
+            .map(parse_hex)
+            .collect::<Result<Vec<_>, _>>()?;
+        if colors.is_empty() {
+            return Err("empty palette".to_string());
+        }
+        Ok(Palette::new(colors))
+    }
+}
+
+impl fmt::Display for Palette {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, [r, g, b]) in self.colors.iter().enumerate() {
+            if i > 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "#{:02x}{:02x}{:02x}", r, g, b)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn palette_test() {
+        let palette = Palette::web216();
+        assert_eq!(palette.colors().len(), 216);
+        let converter = ColorConverter::new();
+        let color = ColorU8::from_rgba(250, 10, 60, 77);
+        assert_eq!(
+            palette.snap(&converter, &color),
+            ColorU8::from_rgba(255, 0, 51, 77)
+        );
+
+        let palette: Palette = "#ff0000, 00ff00\n#00f".parse().unwrap();
+        assert_eq!(palette.colors(), &[[255, 0, 0], [0, 255, 0], [0, 0, 255]]);
+        assert_eq!(palette.to_string().parse::<Palette>().unwrap(), palette);
+        assert!("#ff00".parse::<Palette>().is_err());
+        assert!("".parse::<Palette>().is_err());
+    }
+
+    #[test]
+    fn kmeans_test() {
+        let converter = ColorConverter::new();
+        let mut target = Vec::new();
+        for i in 0..100 {
+            let rgba = if i % 3 == 0 {
+                [200, 30, 30, 255]
+            } else {
+                [20, 20, 120, 255]
+            };
+            target.extend_from_slice(&rgba);
+        }
+        let palette = Palette::kmeans(&converter, &target, 2, 7);
+        let mut colors = palette.colors().to_vec();
+        colors.sort();
+        assert_eq!(colors, vec![[20, 20, 120], [200, 30, 30]]);
+
+        // asking for more colors than there are stops early
+        assert_eq!(Palette::kmeans(&converter, &target, 5, 7).colors().len(), 2);
+    }
+}
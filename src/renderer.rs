@@ -1,7 +1,10 @@
 use tiny_skia::*;
 
+use crate::coverage::Coverage;
+
 const PI: f32 = std::f32::consts::PI;
 
+#[allow(clippy::too_many_arguments)]
 pub fn fill_ellipse(
     pixmap: &mut Pixmap,
     cx: f32,
@@ -27,33 +30,147 @@ pub fn fill_ellipse(
 
     let path = PathBuilder::from_circle(0.0, 0.0, r).unwrap();
 
+    let scale = Transform::from_scale(sx, sy);
+    let transform = scale.post_concat(rotate_translate(cx, cy, angle_degree));
+    fill_path(pixmap, &path, color, is_antialias, transform);
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn fill_rect(
+    pixmap: &mut Pixmap,
+    cx: f32,
+    cy: f32,
+    rx: f32,
+    ry: f32,
+    angle_degree: f32,
+    color: &ColorU8,
+    is_antialias: bool,
+) {
+    if rx == 0.0 || ry == 0.0 {
+        return;
+    }
+
+    let rect = Rect::from_ltrb(-rx, -ry, rx, ry).unwrap();
+    let path = PathBuilder::from_rect(rect);
+
+    let transform = rotate_translate(cx, cy, angle_degree);
+    fill_path(pixmap, &path, color, is_antialias, transform);
+}
+
+pub fn fill_polygon(
+    pixmap: &mut Pixmap,
+    points: &[(f32, f32)],
+    color: &ColorU8,
+    is_antialias: bool,
+) {
+    let mut pb = PathBuilder::new();
+    for (i, &(x, y)) in points.iter().enumerate() {
+        if i == 0 {
+            pb.move_to(x, y);
+        } else {
+            pb.line_to(x, y);
+        }
+    }
+    pb.close();
+    if let Some(path) = pb.finish() {
+        fill_path(pixmap, &path, color, is_antialias, Transform::identity());
+    }
+}
+
+fn rotate_translate(cx: f32, cy: f32, angle_degree: f32) -> Transform {
     let angle_radian = angle_degree * PI / 180.0;
     let sc = angle_radian.sin_cos();
     let sin = sc.0;
     let cos = sc.1;
     let rotate = Transform::from_row(cos, sin, -sin, cos, 0.0, 0.0);
 
-    let scale = Transform::from_scale(sx, sy);
     let t = Transform::from_translate(cx, cy);
-    let mut transform = scale.post_concat(rotate);
-    transform = transform.post_concat(t);
+    rotate.post_concat(t)
+}
 
-    //canvas.set_transform(transform);
+fn fill_path(
+    pixmap: &mut Pixmap,
+    path: &Path,
+    color: &ColorU8,
+    is_antialias: bool,
+    transform: Transform,
+) {
+    let mut paint = Paint::default();
+    paint.set_color_rgba8(color.red(), color.green(), color.blue(), color.alpha());
+    paint.anti_alias = is_antialias;
+
+    pixmap.fill_path(path, &paint, FillRule::Winding, transform, None);
+}
 
+pub fn stroke_path(
+    pixmap: &mut Pixmap,
+    path: &Path,
+    width: f32,
+    color: &ColorU8,
+    is_antialias: bool,
+) {
     let mut paint = Paint::default();
     paint.set_color_rgba8(color.red(), color.green(), color.blue(), color.alpha());
     paint.anti_alias = is_antialias;
 
-    pixmap.fill_path(&path, &paint, FillRule::Winding, transform, None);
-    //canvas.fill_path(&path, &paint, FillRule::Winding);
+    let stroke = Stroke {
+        width,
+        ..Stroke::default()
+    };
+
+    pixmap.stroke_path(path, &paint, &stroke, Transform::identity(), None);
+}
+
+// `color` drawn over the opaque pixel `dst` with coverage `c`
+pub fn blend(dst: PremultipliedColorU8, color: &ColorU8, c: f32) -> PremultipliedColorU8 {
+    let a = color.alpha() as f32 / 255.0 * c;
+    let mix = |d: u8, s: u8| (d as f32 + (s as f32 - d as f32) * a).round() as u8;
+    PremultipliedColorU8::from_rgba(
+        mix(dst.red(), color.red()),
+        mix(dst.green(), color.green()),
+        mix(dst.blue(), color.blue()),
+        0xff,
+    )
+    .unwrap()
+}
+
+pub fn fill_coverage(pixmap: &mut Pixmap, coverage: &Coverage, color: &ColorU8) {
+    let w = pixmap.width();
+    let pixels = pixmap.pixels_mut();
+    for span in coverage.spans() {
+        for (x, &c) in (span.x0..span.x1).zip(coverage.values(span).iter()) {
+            let index = (x + w * span.y) as usize;
+            pixels[index] = blend(pixels[index], color, c);
+        }
+    }
+}
 
-    //canvas.reset_transform();
+// `fill_coverage` limited to `rect` (x0, y0, x1, y1), for spans in row order
+pub fn fill_coverage_clipped(
+    pixmap: &mut Pixmap,
+    coverage: &Coverage,
+    color: &ColorU8,
+    rect: (u32, u32, u32, u32),
+) {
+    let (x0, y0, x1, y1) = rect;
+    let w = pixmap.width();
+    let pixels = pixmap.pixels_mut();
+    let spans = coverage.spans();
+    let first = spans.partition_point(|span| span.y < y0);
+    for span in spans[first..].iter().take_while(|span| span.y < y1) {
+        let values = coverage.values(span);
+        for x in span.x0.max(x0)..span.x1.min(x1) {
+            let index = (x + w * span.y) as usize;
+            pixels[index] = blend(pixels[index], color, values[(x - span.x0) as usize]);
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn fill_ellipse_test() {
         let mut pixmap = Pixmap::new(16, 16).unwrap();
         fill_ellipse(
@@ -74,4 +191,26 @@ mod tests {
         assert_eq!(color.green(), 200);
         assert_eq!(color.blue(), 100);
     }
+
+    #[test]
+    fn fill_coverage_test() {
+        let mut pixmap = Pixmap::new(4, 1).unwrap();
+        pixmap.fill(Color::from_rgba8(0, 100, 200, 255));
+        let mut coverage = Coverage::new();
+        coverage.push_span(0, 1, &[1.0, 0.5]);
+
+        fill_coverage(
+            &mut pixmap,
+            &coverage,
+            &ColorU8::from_rgba(200, 100, 0, 128),
+        );
+
+        let color = pixmap.pixel(0, 0).unwrap();
+        assert_eq!((color.red(), color.green(), color.blue()), (0, 100, 200));
+        let color = pixmap.pixel(1, 0).unwrap();
+        assert_eq!((color.red(), color.green(), color.blue()), (100, 100, 100));
+        let color = pixmap.pixel(2, 0).unwrap();
+        assert_eq!((color.red(), color.green(), color.blue()), (50, 100, 150));
+        assert!(color.is_opaque());
+    }
 }
@@ -0,0 +1,649 @@
+use std::fmt;
+use std::str::FromStr;
+
+use tiny_skia::*;
+
+use rand::distributions::{Distribution, Uniform};
+use rand::Rng;
+
+mod circle;
+mod curve;
+mod ellipse;
+mod rectangle;
+mod rotated_rectangle;
+mod triangle;
+
+pub use self::circle::Circle;
+pub use self::curve::{CubicBezier, QuadraticBezier};
+pub use self::ellipse::Ellipse;
+pub use self::rectangle::Rectangle;
+pub use self::rotated_rectangle::RotatedRectangle;
+pub use self::triangle::Triangle;
+
+pub(crate) fn rnd<T>(rng: &mut rand::rngs::StdRng, min: T, max: T) -> T
+where
+    T: rand::distributions::uniform::SampleUniform,
+{
+    let range = Uniform::new_inclusive(min, max);
+    range.sample(rng)
+}
+
+pub(crate) fn clamp<T>(value: T, min: T, max: T) -> T
+where
+    T: std::cmp::PartialOrd,
+{
+    if value < min {
+        min
+    } else if value > max {
+        max
+    } else {
+        value
+    }
+}
+
+pub(crate) fn mod_angle(angle: f32) -> f32 {
+    angle.rem_euclid(180.0)
+}
+
+// standard normal distribution (Box-Muller)
+pub(crate) fn gaussian(rng: &mut rand::rngs::StdRng) -> f32 {
+    let u1 = 1.0 - rng.gen::<f32>();
+    let u2 = rng.gen::<f32>();
+    (-2.0 * u1.ln()).sqrt() * (2.0 * std::f32::consts::PI * u2).cos()
+}
+
+// returns value + d and value - d for a gaussian step d
+pub(crate) fn mutate_value(
+    rng: &mut rand::rngs::StdRng,
+    value: f32,
+    sigma: f32,
+    min: f32,
+    max: f32,
+) -> (f32, f32) {
+    let d = sigma * gaussian(rng);
+    (clamp(value + d, min, max), clamp(value - d, min, max))
+}
+
+fn brush_size(t_ratio: f32, brush_scale: f32, image_size: u32) -> f32 {
+    let t1 = brush_scale * (1.0 - t_ratio);
+    let t2 = t1 * t1;
+    let size_ratio = t2;
+    1.0 + size_ratio * 0.5 * (image_size as f32)
+}
+
+// image size and brush schedule a shape is created or mutated with
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Brush {
+    pub w: u32,
+    pub h: u32,
+    pub t_ratio: f32,
+    pub brush_scale: f32,
+}
+
+impl Brush {
+    pub fn new(w: u32, h: u32, t_ratio: f32, brush_scale: f32) -> Brush {
+        Brush {
+            w,
+            h,
+            t_ratio,
+            brush_scale,
+        }
+    }
+
+    pub fn size_x(&self) -> f32 {
+        brush_size(self.t_ratio, self.brush_scale, self.w - 1)
+    }
+
+    pub fn size_y(&self) -> f32 {
+        brush_size(self.t_ratio, self.brush_scale, self.h - 1)
+    }
+
+    pub fn max_x(&self) -> f32 {
+        (self.w - 1) as f32
+    }
+
+    pub fn max_y(&self) -> f32 {
+        (self.h - 1) as f32
+    }
+
+    // mutation step sizes shrink with the brush so late shapes can be fine tuned
+    pub fn position_sigma_x(&self) -> f32 {
+        0.1 * self.size_x()
+    }
+
+    pub fn position_sigma_y(&self) -> f32 {
+        0.1 * self.size_y()
+    }
+
+    pub fn radius_sigma_x(&self) -> f32 {
+        0.25 * self.size_x()
+    }
+
+    pub fn radius_sigma_y(&self) -> f32 {
+        0.25 * self.size_y()
+    }
+}
+
+pub(crate) fn rand_point(
+    brush: &Brush,
+    x: f32,
+    y: f32,
+    rng: &mut rand::rngs::StdRng,
+) -> (f32, f32) {
+    let size_x = brush.size_x();
+    let size_y = brush.size_y();
+    (
+        clamp(x + rnd(rng, -size_x, size_x), 0.0, brush.max_x()),
+        clamp(y + rnd(rng, -size_y, size_y), 0.0, brush.max_y()),
+    )
+}
+
+// moves coordinate `index` of `points` (x1, y1, x2, y2, ...)
+pub(crate) fn mutate_point(
+    points1: &mut [f32],
+    points2: &mut [f32],
+    index: usize,
+    brush: &Brush,
+    rng: &mut rand::rngs::StdRng,
+) {
+    let axis = index % 2;
+    let (sigma, limit) = if axis == 0 {
+        (brush.position_sigma_x(), brush.max_x())
+    } else {
+        (brush.position_sigma_y(), brush.max_y())
+    };
+    let (v1, v2) = mutate_value(rng, points1[index], sigma, 0.0, limit);
+    points1[index] = v1;
+    points2[index] = v2;
+}
+
+// radii of a shape centered on the anchor, up to the brush size
+pub(crate) fn rand_radii(brush: &Brush, rng: &mut rand::rngs::StdRng) -> (f32, f32) {
+    let rx_max = brush.size_x();
+    let ry_max = brush.size_y();
+    let rx_min = 1.0 + rx_max / 16.0;
+    let ry_min = 1.0 + ry_max / 16.0;
+    let rx = rnd(rng, rx_min, rx_max.max(rx_min));
+    let ry = rnd(rng, ry_min, ry_max.max(ry_min));
+    (rx, ry)
+}
+
+// moves one of x, y, rx, ry and, for rotated shapes, the angle of
+// `params` (x, y, rx, ry[, angle])
+pub(crate) fn mutate_center_radii(
+    params1: &mut [f32],
+    params2: &mut [f32],
+    brush: &Brush,
+    rng: &mut rand::rngs::StdRng,
+) {
+    let prop = rnd(rng, 0, params1.len() as i32 - 1) as usize;
+    let (sigma, min, max) = match prop {
+        0 => (brush.position_sigma_x(), 0.0, brush.max_x()),
+        1 => (brush.position_sigma_y(), 0.0, brush.max_y()),
+        2 => (brush.radius_sigma_x(), MIN_RADIUS, brush.max_x() / 2.0),
+        3 => (brush.radius_sigma_y(), MIN_RADIUS, brush.max_y() / 2.0),
+        4 => (ANGLE_SIGMA, f32::MIN, f32::MAX),
+        _ => unreachable!("prop is out of range"),
+    };
+    let (v1, v2) = mutate_value(rng, params1[prop], sigma, min, max);
+    if prop == 4 {
+        params1[prop] = mod_angle(v1);
+        params2[prop] = mod_angle(v2);
+    } else {
+        params1[prop] = v1;
+        params2[prop] = v2;
+    }
+}
+
+pub(crate) const ANGLE_SIGMA: f32 = 4.0; // degree
+pub(crate) const MIN_RADIUS: f32 = 0.5;
+// short / long radius of a shape oriented along a fully coherent edge
+const MIN_ASPECT: f32 = 0.25;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BoundingBox {
+    pub left: f32,
+    pub top: f32,
+    pub right: f32,
+    pub bottom: f32,
+}
+
+impl BoundingBox {
+    pub fn from_center(cx: f32, cy: f32, ex: f32, ey: f32) -> BoundingBox {
+        BoundingBox {
+            left: cx - ex,
+            top: cy - ey,
+            right: cx + ex,
+            bottom: cy + ey,
+        }
+    }
+
+    pub fn from_points(points: &[(f32, f32)]) -> BoundingBox {
+        let mut bbox = BoundingBox {
+            left: f32::MAX,
+            top: f32::MAX,
+            right: f32::MIN,
+            bottom: f32::MIN,
+        };
+        for &(x, y) in points {
+            bbox.left = bbox.left.min(x);
+            bbox.top = bbox.top.min(y);
+            bbox.right = bbox.right.max(x);
+            bbox.bottom = bbox.bottom.max(y);
+        }
+        bbox
+    }
+}
+
+pub trait Shape: Copy {
+    // (x, y) is the point the shape is anchored at
+    fn rand(brush: &Brush, x: f32, y: f32, rng: &mut rand::rngs::StdRng) -> Self;
+    // returns a mutation and its inverse
+    fn mutate(&self, brush: &Brush, rng: &mut rand::rngs::StdRng) -> (Self, Self);
+    fn draw(&self, pixmap: &mut Pixmap, color: &ColorU8, is_antialias: bool);
+    fn svg(&self, color: &ColorU8, precision: usize) -> String;
+    fn bounding_box(&self) -> BoundingBox;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShapeType {
+    Ellipse,
+    Circle,
+    Triangle,
+    Rectangle,
+    RotatedRectangle,
+    Quadratic,
+    Cubic,
+    // a different primitive for each candidate
+    Any,
+}
+
+impl ShapeType {
+    pub const ALL: [ShapeType; 7] = [
+        ShapeType::Ellipse,
+        ShapeType::Circle,
+        ShapeType::Triangle,
+        ShapeType::Rectangle,
+        ShapeType::RotatedRectangle,
+        ShapeType::Quadratic,
+        ShapeType::Cubic,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ShapeType::Ellipse => "ellipse",
+            ShapeType::Circle => "circle",
+            ShapeType::Triangle => "triangle",
+            ShapeType::Rectangle => "rectangle",
+            ShapeType::RotatedRectangle => "rotated-rectangle",
+            ShapeType::Quadratic => "quadratic",
+            ShapeType::Cubic => "cubic",
+            ShapeType::Any => "any",
+        }
+    }
+}
+
+impl fmt::Display for ShapeType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for ShapeType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ShapeType, String> {
+        if s == "any" {
+            return Ok(ShapeType::Any);
+        }
+        for &shape_type in ShapeType::ALL.iter() {
+            if s == shape_type.name() {
+                return Ok(shape_type);
+            }
+        }
+        Err(format!("unknown shape: {}", s))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Primitive {
+    Ellipse(Ellipse),
+    Circle(Circle),
+    Triangle(Triangle),
+    Rectangle(Rectangle),
+    RotatedRectangle(RotatedRectangle),
+    Quadratic(QuadraticBezier),
+    Cubic(CubicBezier),
+}
+
+impl Primitive {
+    pub fn rand(
+        shape_type: ShapeType,
+        brush: &Brush,
+        x: f32,
+        y: f32,
+        rng: &mut rand::rngs::StdRng,
+    ) -> Primitive {
+        match shape_type {
+            ShapeType::Ellipse => Primitive::Ellipse(Ellipse::rand(brush, x, y, rng)),
+            ShapeType::Circle => Primitive::Circle(Circle::rand(brush, x, y, rng)),
+            ShapeType::Triangle => Primitive::Triangle(Triangle::rand(brush, x, y, rng)),
+            ShapeType::Rectangle => Primitive::Rectangle(Rectangle::rand(brush, x, y, rng)),
+            ShapeType::RotatedRectangle => {
+                Primitive::RotatedRectangle(RotatedRectangle::rand(brush, x, y, rng))
+            }
+            ShapeType::Quadratic => Primitive::Quadratic(QuadraticBezier::rand(brush, x, y, rng)),
+            ShapeType::Cubic => Primitive::Cubic(CubicBezier::rand(brush, x, y, rng)),
+            ShapeType::Any => {
+                let index = rnd(rng, 0, ShapeType::ALL.len() - 1);
+                Primitive::rand(ShapeType::ALL[index], brush, x, y, rng)
+            }
+        }
+    }
+
+    pub fn shape_type(&self) -> ShapeType {
+        match self {
+            Primitive::Ellipse(_) => ShapeType::Ellipse,
+            Primitive::Circle(_) => ShapeType::Circle,
+            Primitive::Triangle(_) => ShapeType::Triangle,
+            Primitive::Rectangle(_) => ShapeType::Rectangle,
+            Primitive::RotatedRectangle(_) => ShapeType::RotatedRectangle,
+            Primitive::Quadratic(_) => ShapeType::Quadratic,
+            Primitive::Cubic(_) => ShapeType::Cubic,
+        }
+    }
+
+    pub fn mutate(&self, brush: &Brush, rng: &mut rand::rngs::StdRng) -> (Primitive, Primitive) {
+        match self {
+            Primitive::Ellipse(s) => {
+                let (s1, s2) = s.mutate(brush, rng);
+                (Primitive::Ellipse(s1), Primitive::Ellipse(s2))
+            }
+            Primitive::Circle(s) => {
+                let (s1, s2) = s.mutate(brush, rng);
+                (Primitive::Circle(s1), Primitive::Circle(s2))
+            }
+            Primitive::Triangle(s) => {
+                let (s1, s2) = s.mutate(brush, rng);
+                (Primitive::Triangle(s1), Primitive::Triangle(s2))
+            }
+            Primitive::Rectangle(s) => {
+                let (s1, s2) = s.mutate(brush, rng);
+                (Primitive::Rectangle(s1), Primitive::Rectangle(s2))
+            }
+            Primitive::RotatedRectangle(s) => {
+                let (s1, s2) = s.mutate(brush, rng);
+                (
+                    Primitive::RotatedRectangle(s1),
+                    Primitive::RotatedRectangle(s2),
+                )
+            }
+            Primitive::Quadratic(s) => {
+                let (s1, s2) = s.mutate(brush, rng);
+                (Primitive::Quadratic(s1), Primitive::Quadratic(s2))
+            }
+            Primitive::Cubic(s) => {
+                let (s1, s2) = s.mutate(brush, rng);
+                (Primitive::Cubic(s1), Primitive::Cubic(s2))
+            }
+        }
+    }
+
+    pub fn draw(&self, pixmap: &mut Pixmap, color: &ColorU8, is_antialias: bool) {
+        match self {
+            Primitive::Ellipse(s) => s.draw(pixmap, color, is_antialias),
+            Primitive::Circle(s) => s.draw(pixmap, color, is_antialias),
+            Primitive::Triangle(s) => s.draw(pixmap, color, is_antialias),
+            Primitive::Rectangle(s) => s.draw(pixmap, color, is_antialias),
+            Primitive::RotatedRectangle(s) => s.draw(pixmap, color, is_antialias),
+            Primitive::Quadratic(s) => s.draw(pixmap, color, is_antialias),
+            Primitive::Cubic(s) => s.draw(pixmap, color, is_antialias),
+        }
+    }
+
+    pub fn svg(&self, color: &ColorU8, precision: usize) -> String {
+        match self {
+            Primitive::Ellipse(s) => s.svg(color, precision),
+            Primitive::Circle(s) => s.svg(color, precision),
+            Primitive::Triangle(s) => s.svg(color, precision),
+            Primitive::Rectangle(s) => s.svg(color, precision),
+            Primitive::RotatedRectangle(s) => s.svg(color, precision),
+            Primitive::Quadratic(s) => s.svg(color, precision),
+            Primitive::Cubic(s) => s.svg(color, precision),
+        }
+    }
+
+    pub fn bounding_box(&self) -> BoundingBox {
+        match self {
+            Primitive::Ellipse(s) => s.bounding_box(),
+            Primitive::Circle(s) => s.bounding_box(),
+            Primitive::Triangle(s) => s.bounding_box(),
+            Primitive::Rectangle(s) => s.bounding_box(),
+            Primitive::RotatedRectangle(s) => s.bounding_box(),
+            Primitive::Quadratic(s) => s.bounding_box(),
+            Primitive::Cubic(s) => s.bounding_box(),
+        }
+    }
+
+    // geometry as a flat list, in field order
+    pub fn params(&self) -> Vec<f32> {
+        match self {
+            Primitive::Ellipse(s) => vec![s.x, s.y, s.rx, s.ry, s.angle],
+            Primitive::Circle(s) => vec![s.x, s.y, s.r],
+            Primitive::Triangle(s) => s.points.to_vec(),
+            Primitive::Rectangle(s) => vec![s.x, s.y, s.rx, s.ry],
+            Primitive::RotatedRectangle(s) => vec![s.x, s.y, s.rx, s.ry, s.angle],
+            Primitive::Quadratic(s) => {
+                let mut params = s.points.to_vec();
+                params.push(s.width);
+                params
+            }
+            Primitive::Cubic(s) => {
+                let mut params = s.points.to_vec();
+                params.push(s.width);
+                params
+            }
+        }
+    }
+
+    // inverse of `params`
+    pub fn from_params(shape_type: ShapeType, p: &[f32]) -> Option<Primitive> {
+        let len = match shape_type {
+            ShapeType::Ellipse => 5,
+            ShapeType::Circle => 3,
+            ShapeType::Triangle => 6,
+            ShapeType::Rectangle => 4,
+            ShapeType::RotatedRectangle => 5,
+            ShapeType::Quadratic => 7,
+            ShapeType::Cubic => 9,
+            ShapeType::Any => return None,
+        };
+        if p.len() != len {
+            return None;
+        }
+        let shape = match shape_type {
+            ShapeType::Ellipse => Primitive::Ellipse(Ellipse {
+                x: p[0],
+                y: p[1],
+                rx: p[2],
+                ry: p[3],
+                angle: p[4],
+            }),
+            ShapeType::Circle => Primitive::Circle(Circle {
+                x: p[0],
+                y: p[1],
+                r: p[2],
+            }),
+            ShapeType::Triangle => {
+                let mut points = [0.0; 6];
+                points.copy_from_slice(p);
+                Primitive::Triangle(Triangle { points })
+            }
+            ShapeType::Rectangle => Primitive::Rectangle(Rectangle {
+                x: p[0],
+                y: p[1],
+                rx: p[2],
+                ry: p[3],
+            }),
+            ShapeType::RotatedRectangle => Primitive::RotatedRectangle(RotatedRectangle {
+                x: p[0],
+                y: p[1],
+                rx: p[2],
+                ry: p[3],
+                angle: p[4],
+            }),
+            ShapeType::Quadratic => {
+                let mut points = [0.0; 6];
+                points.copy_from_slice(&p[..6]);
+                Primitive::Quadratic(QuadraticBezier {
+                    points,
+                    width: p[6],
+                })
+            }
+            ShapeType::Cubic => {
+                let mut points = [0.0; 8];
+                points.copy_from_slice(&p[..8]);
+                Primitive::Cubic(CubicBezier {
+                    points,
+                    width: p[8],
+                })
+            }
+            ShapeType::Any => unreachable!(),
+        };
+        Some(shape)
+    }
+
+    // the same shape with every length multiplied by `factor`
+    pub fn scaled(&self, factor: f32) -> Primitive {
+        let mut params = self.params();
+        let lengths = match self {
+            // the last param is the angle
+            Primitive::Ellipse(_) | Primitive::RotatedRectangle(_) => params.len() - 1,
+            _ => params.len(),
+        };
+        for p in params[..lengths].iter_mut() {
+            *p *= factor;
+        }
+        Primitive::from_params(self.shape_type(), &params).unwrap()
+    }
+
+    // turns the long axis to `angle` (degree) and stretches the shape more
+    // the more `coherence` (0.0 - 1.0) the local structure has. Only
+    // ellipses and rotated rectangles have an orientation.
+    pub fn orient(&mut self, angle: f32, coherence: f32) {
+        let (rx, ry, shape_angle) = match self {
+            Primitive::Ellipse(s) => (&mut s.rx, &mut s.ry, &mut s.angle),
+            Primitive::RotatedRectangle(s) => (&mut s.rx, &mut s.ry, &mut s.angle),
+            _ => return,
+        };
+        if coherence <= 0.0 {
+            return;
+        }
+        let long = rx.max(*ry);
+        let aspect = rx.min(*ry) / long;
+        *rx = long;
+        *ry = long * (aspect * (1.0 - coherence) + MIN_ASPECT * coherence);
+        *shape_angle = mod_angle(angle);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    #[test]
+    fn bounding_box_test() {
+        const WIDTH: u32 = 64;
+        const HEIGHT: u32 = 64;
+
+        let brush = Brush::new(WIDTH, HEIGHT, 0.0, 0.75);
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+        let color = ColorU8::from_rgba(255, 255, 255, 255);
+        for &shape_type in ShapeType::ALL.iter() {
+            for _ in 0..16 {
+                let x = rnd(&mut rng, 0.0, WIDTH as f32);
+                let y = rnd(&mut rng, 0.0, HEIGHT as f32);
+                let shape = Primitive::rand(shape_type, &brush, x, y, &mut rng);
+                assert_eq!(shape.shape_type(), shape_type);
+
+                let mut pixmap = Pixmap::new(WIDTH, HEIGHT).unwrap();
+                shape.draw(&mut pixmap, &color, true);
+
+                let bbox = shape.bounding_box();
+                for py in 0..HEIGHT {
+                    for px in 0..WIDTH {
+                        if pixmap.pixel(px, py).unwrap().alpha() == 0 {
+                            continue;
+                        }
+                        assert!((px as f32) >= bbox.left.floor() - 1.0, "{:?}", shape);
+                        assert!((px as f32) < bbox.right.ceil() + 1.0, "{:?}", shape);
+                        assert!((py as f32) >= bbox.top.floor() - 1.0, "{:?}", shape);
+                        assert!((py as f32) < bbox.bottom.ceil() + 1.0, "{:?}", shape);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn scaled_test() {
+        let shape = Primitive::RotatedRectangle(RotatedRectangle {
+            x: 1.0,
+            y: 2.0,
+            rx: 3.0,
+            ry: 4.0,
+            angle: 30.0,
+        });
+        assert_eq!(shape.scaled(2.0).params(), vec![2.0, 4.0, 6.0, 8.0, 30.0]);
+
+        let shape = Primitive::Quadratic(QuadraticBezier {
+            points: [0.0, 1.0, 2.0, 3.0, 4.0, 5.0],
+            width: 0.5,
+        });
+        let expected = vec![0.0, 0.5, 1.0, 1.5, 2.0, 2.5, 0.25];
+        assert_eq!(shape.scaled(0.5).params(), expected);
+    }
+
+    #[test]
+    fn mutate_center_radii_test() {
+        let brush = Brush::new(64, 64, 0.5, 0.75);
+        let mut rng = rand::rngs::StdRng::seed_from_u64(3);
+        let params = [10.0, 20.0, 5.0, 6.0, 179.0];
+        for _ in 0..64 {
+            let mut params1 = params;
+            let mut params2 = params;
+            mutate_center_radii(&mut params1, &mut params2, &brush, &mut rng);
+            // one value moves, to both sides
+            let changed: Vec<usize> = (0..5).filter(|&i| params1[i] != params[i]).collect();
+            assert!(changed.len() <= 1);
+            for &i in changed.iter() {
+                assert_ne!(params2[i], params[i]);
+                assert!(i != 4 || (0.0..180.0).contains(&params1[i]));
+            }
+        }
+    }
+
+    #[test]
+    fn orient_test() {
+        let mut shape = Primitive::Ellipse(Ellipse {
+            x: 1.0,
+            y: 2.0,
+            rx: 2.0,
+            ry: 4.0,
+            angle: 30.0,
+        });
+        // no structure keeps the random shape
+        shape.orient(90.0, 0.0);
+        assert_eq!(shape.params(), vec![1.0, 2.0, 2.0, 4.0, 30.0]);
+        shape.orient(-45.0, 1.0);
+        assert_eq!(shape.params(), vec![1.0, 2.0, 4.0, 1.0, 135.0]);
+
+        let mut shape = Primitive::Circle(Circle {
+            x: 1.0,
+            y: 2.0,
+            r: 3.0,
+        });
+        shape.orient(45.0, 1.0);
+        assert_eq!(shape.params(), vec![1.0, 2.0, 3.0]);
+    }
+}
@@ -0,0 +1,76 @@
+use tiny_skia::*;
+
+use super::{mutate_value, rnd, BoundingBox, Brush, Shape, MIN_RADIUS};
+use crate::renderer;
+use crate::svg::{fmt_num, paint_attributes};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Circle {
+    pub x: f32,
+    pub y: f32,
+    pub r: f32,
+}
+
+impl Shape for Circle {
+    fn rand(brush: &Brush, x: f32, y: f32, rng: &mut rand::rngs::StdRng) -> Circle {
+        let r_max = brush.size_x().min(brush.size_y());
+        let r_min = 1.0 + r_max / 16.0;
+        let r = rnd(rng, r_min, r_max.max(r_min));
+        Circle { x, y, r }
+    }
+
+    fn mutate(&self, brush: &Brush, rng: &mut rand::rngs::StdRng) -> (Circle, Circle) {
+        let mut s1 = *self;
+        //inverse shape
+        let mut s2 = *self;
+
+        let prop = rnd(rng, 0, 2) as u8;
+        if prop == 0 {
+            let sigma = brush.position_sigma_x();
+            let (x1, x2) = mutate_value(rng, self.x, sigma, 0.0, brush.max_x());
+            s1.x = x1;
+            s2.x = x2;
+        } else if prop == 1 {
+            let sigma = brush.position_sigma_y();
+            let (y1, y2) = mutate_value(rng, self.y, sigma, 0.0, brush.max_y());
+            s1.y = y1;
+            s2.y = y2;
+        } else if prop == 2 {
+            let r_limit = (brush.max_x().min(brush.max_y()) / 2.0).max(MIN_RADIUS);
+            let sigma = brush.radius_sigma_x().min(brush.radius_sigma_y());
+            let (r1, r2) = mutate_value(rng, self.r, sigma, MIN_RADIUS, r_limit);
+            s1.r = r1;
+            s2.r = r2;
+        } else {
+            unreachable!("prop is out of range");
+        }
+        (s1, s2)
+    }
+
+    fn draw(&self, pixmap: &mut Pixmap, color: &ColorU8, is_antialias: bool) {
+        renderer::fill_ellipse(
+            pixmap,
+            self.x,
+            self.y,
+            self.r,
+            self.r,
+            0.0,
+            color,
+            is_antialias,
+        );
+    }
+
+    fn svg(&self, color: &ColorU8, precision: usize) -> String {
+        format!(
+            r#"<circle cx="{}" cy="{}" r="{}" {}/>"#,
+            fmt_num(self.x, precision),
+            fmt_num(self.y, precision),
+            fmt_num(self.r, precision),
+            paint_attributes("fill", color)
+        )
+    }
+
+    fn bounding_box(&self) -> BoundingBox {
+        BoundingBox::from_center(self.x, self.y, self.r, self.r)
+    }
+}
@@ -0,0 +1,180 @@
+use tiny_skia::*;
+
+use super::{mutate_point, mutate_value, rand_point, rnd, BoundingBox, Brush, Shape};
+use crate::renderer;
+use crate::svg::{fmt_num, paint_attributes};
+
+const MIN_WIDTH: f32 = 0.5;
+
+fn rand_width(brush: &Brush, rng: &mut rand::rngs::StdRng) -> f32 {
+    let width_max = 1.0 + brush.size_x().min(brush.size_y()) / 8.0;
+    rnd(rng, 1.0, width_max)
+}
+
+// moves one control point coordinate or changes the width
+fn mutate_curve(
+    points1: &mut [f32],
+    points2: &mut [f32],
+    width1: &mut f32,
+    width2: &mut f32,
+    brush: &Brush,
+    rng: &mut rand::rngs::StdRng,
+) {
+    let prop = rnd(rng, 0, points1.len());
+    if prop == points1.len() {
+        let size = brush.size_x().min(brush.size_y());
+        let width_max = 1.0 + size / 2.0;
+        let (v1, v2) = mutate_value(rng, *width1, 0.1 * size, MIN_WIDTH, width_max);
+        *width1 = v1;
+        *width2 = v2;
+    } else {
+        mutate_point(points1, points2, prop, brush, rng);
+    }
+}
+
+fn stroke_bounding_box(points: &[(f32, f32)], width: f32) -> BoundingBox {
+    let mut bbox = BoundingBox::from_points(points);
+    let r = 0.5 * width;
+    bbox.left -= r;
+    bbox.top -= r;
+    bbox.right += r;
+    bbox.bottom += r;
+    bbox
+}
+
+fn svg_path(d: &str, color: &ColorU8, width: f32, precision: usize) -> String {
+    format!(
+        r#"<path d="{}" fill="none" {} stroke-width="{}"/>"#,
+        d,
+        paint_attributes("stroke", color),
+        fmt_num(width, precision)
+    )
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct QuadraticBezier {
+    pub points: [f32; 6], // x1, y1, x2, y2, x3, y3
+    pub width: f32,
+}
+
+impl QuadraticBezier {
+    fn points(&self) -> [(f32, f32); 3] {
+        let p = &self.points;
+        [(p[0], p[1]), (p[2], p[3]), (p[4], p[5])]
+    }
+}
+
+impl Shape for QuadraticBezier {
+    fn rand(brush: &Brush, x: f32, y: f32, rng: &mut rand::rngs::StdRng) -> QuadraticBezier {
+        let (x2, y2) = rand_point(brush, x, y, rng);
+        let (x3, y3) = rand_point(brush, x, y, rng);
+        let width = rand_width(brush, rng);
+        QuadraticBezier {
+            points: [x, y, x2, y2, x3, y3],
+            width,
+        }
+    }
+
+    fn mutate(
+        &self,
+        brush: &Brush,
+        rng: &mut rand::rngs::StdRng,
+    ) -> (QuadraticBezier, QuadraticBezier) {
+        let mut s1 = *self;
+        //inverse shape
+        let mut s2 = *self;
+        mutate_curve(
+            &mut s1.points,
+            &mut s2.points,
+            &mut s1.width,
+            &mut s2.width,
+            brush,
+            rng,
+        );
+        (s1, s2)
+    }
+
+    fn draw(&self, pixmap: &mut Pixmap, color: &ColorU8, is_antialias: bool) {
+        let p = self.points();
+        let mut pb = PathBuilder::new();
+        pb.move_to(p[0].0, p[0].1);
+        pb.quad_to(p[1].0, p[1].1, p[2].0, p[2].1);
+        if let Some(path) = pb.finish() {
+            renderer::stroke_path(pixmap, &path, self.width, color, is_antialias);
+        }
+    }
+
+    fn svg(&self, color: &ColorU8, precision: usize) -> String {
+        let p: Vec<String> = self.points.iter().map(|&v| fmt_num(v, precision)).collect();
+        let d = format!("M{},{} Q{},{} {},{}", p[0], p[1], p[2], p[3], p[4], p[5]);
+        svg_path(&d, color, self.width, precision)
+    }
+
+    fn bounding_box(&self) -> BoundingBox {
+        stroke_bounding_box(&self.points(), self.width)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CubicBezier {
+    pub points: [f32; 8], // x1, y1, x2, y2, x3, y3, x4, y4
+    pub width: f32,
+}
+
+impl CubicBezier {
+    fn points(&self) -> [(f32, f32); 4] {
+        let p = &self.points;
+        [(p[0], p[1]), (p[2], p[3]), (p[4], p[5]), (p[6], p[7])]
+    }
+}
+
+impl Shape for CubicBezier {
+    fn rand(brush: &Brush, x: f32, y: f32, rng: &mut rand::rngs::StdRng) -> CubicBezier {
+        let (x2, y2) = rand_point(brush, x, y, rng);
+        let (x3, y3) = rand_point(brush, x, y, rng);
+        let (x4, y4) = rand_point(brush, x, y, rng);
+        let width = rand_width(brush, rng);
+        CubicBezier {
+            points: [x, y, x2, y2, x3, y3, x4, y4],
+            width,
+        }
+    }
+
+    fn mutate(&self, brush: &Brush, rng: &mut rand::rngs::StdRng) -> (CubicBezier, CubicBezier) {
+        let mut s1 = *self;
+        //inverse shape
+        let mut s2 = *self;
+        mutate_curve(
+            &mut s1.points,
+            &mut s2.points,
+            &mut s1.width,
+            &mut s2.width,
+            brush,
+            rng,
+        );
+        (s1, s2)
+    }
+
+    fn draw(&self, pixmap: &mut Pixmap, color: &ColorU8, is_antialias: bool) {
+        let p = self.points();
+        let mut pb = PathBuilder::new();
+        pb.move_to(p[0].0, p[0].1);
+        pb.cubic_to(p[1].0, p[1].1, p[2].0, p[2].1, p[3].0, p[3].1);
+        if let Some(path) = pb.finish() {
+            renderer::stroke_path(pixmap, &path, self.width, color, is_antialias);
+        }
+    }
+
+    fn svg(&self, color: &ColorU8, precision: usize) -> String {
+        let p: Vec<String> = self.points.iter().map(|&v| fmt_num(v, precision)).collect();
+        let d = format!(
+            "M{},{} C{},{} {},{} {},{}",
+            p[0], p[1], p[2], p[3], p[4], p[5], p[6], p[7]
+        );
+        svg_path(&d, color, self.width, precision)
+    }
+
+    fn bounding_box(&self) -> BoundingBox {
+        stroke_bounding_box(&self.points(), self.width)
+    }
+}
@@ -0,0 +1,94 @@
+use tiny_skia::*;
+
+use super::{mutate_center_radii, rand_radii, rnd, BoundingBox, Brush, Shape};
+use crate::renderer;
+use crate::svg::{fmt_num, paint_attributes};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Ellipse {
+    pub x: f32,
+    pub y: f32,
+    pub rx: f32,
+    pub ry: f32,
+    pub angle: f32, // degree
+}
+
+impl Shape for Ellipse {
+    fn rand(brush: &Brush, x: f32, y: f32, rng: &mut rand::rngs::StdRng) -> Ellipse {
+        let (rx, ry) = rand_radii(brush, rng);
+        let angle = rnd(rng, 0.0, 180.0);
+        Ellipse {
+            x,
+            y,
+            rx,
+            ry,
+            angle,
+        }
+    }
+
+    fn mutate(&self, brush: &Brush, rng: &mut rand::rngs::StdRng) -> (Ellipse, Ellipse) {
+        let mut params1 = [self.x, self.y, self.rx, self.ry, self.angle];
+        //inverse shape
+        let mut params2 = params1;
+        mutate_center_radii(&mut params1, &mut params2, brush, rng);
+        let [x, y, rx, ry, angle] = params1;
+        let s1 = Ellipse {
+            x,
+            y,
+            rx,
+            ry,
+            angle,
+        };
+        let [x, y, rx, ry, angle] = params2;
+        let s2 = Ellipse {
+            x,
+            y,
+            rx,
+            ry,
+            angle,
+        };
+        (s1, s2)
+    }
+
+    fn draw(&self, pixmap: &mut Pixmap, color: &ColorU8, is_antialias: bool) {
+        renderer::fill_ellipse(
+            pixmap,
+            self.x,
+            self.y,
+            self.rx,
+            self.ry,
+            self.angle,
+            color,
+            is_antialias,
+        );
+    }
+
+    fn svg(&self, color: &ColorU8, precision: usize) -> String {
+        let x = fmt_num(self.x, precision);
+        let y = fmt_num(self.y, precision);
+        let angle = fmt_num(self.angle, precision);
+        let transform = if angle == "0" {
+            String::new()
+        } else {
+            format!(r#" transform="rotate({} {} {})""#, angle, x, y)
+        };
+        format!(
+            r#"<ellipse cx="{}" cy="{}" rx="{}" ry="{}" {}{}/>"#,
+            x,
+            y,
+            fmt_num(self.rx, precision),
+            fmt_num(self.ry, precision),
+            paint_attributes("fill", color),
+            transform
+        )
+    }
+
+    fn bounding_box(&self) -> BoundingBox {
+        let (sin, cos) = self.angle.to_radians().sin_cos();
+        let rx = self.rx;
+        let ry = self.ry;
+        let ex = (rx * rx * cos * cos + ry * ry * sin * sin).sqrt();
+        let ey = (rx * rx * sin * sin + ry * ry * cos * cos).sqrt();
+        BoundingBox::from_center(self.x, self.y, ex, ey)
+    }
+}
@@ -0,0 +1,61 @@
+use tiny_skia::*;
+
+use super::{mutate_center_radii, rand_radii, BoundingBox, Brush, Shape};
+use crate::renderer;
+use crate::svg::{fmt_num, paint_attributes};
+
+// axis-aligned, (x, y) is the center
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rectangle {
+    pub x: f32,
+    pub y: f32,
+    pub rx: f32,
+    pub ry: f32,
+}
+
+impl Shape for Rectangle {
+    fn rand(brush: &Brush, x: f32, y: f32, rng: &mut rand::rngs::StdRng) -> Rectangle {
+        let (rx, ry) = rand_radii(brush, rng);
+        Rectangle { x, y, rx, ry }
+    }
+
+    fn mutate(&self, brush: &Brush, rng: &mut rand::rngs::StdRng) -> (Rectangle, Rectangle) {
+        let mut params1 = [self.x, self.y, self.rx, self.ry];
+        //inverse shape
+        let mut params2 = params1;
+        mutate_center_radii(&mut params1, &mut params2, brush, rng);
+        let [x, y, rx, ry] = params1;
+        let s1 = Rectangle { x, y, rx, ry };
+        let [x, y, rx, ry] = params2;
+        let s2 = Rectangle { x, y, rx, ry };
+        (s1, s2)
+    }
+
+    fn draw(&self, pixmap: &mut Pixmap, color: &ColorU8, is_antialias: bool) {
+        renderer::fill_rect(
+            pixmap,
+            self.x,
+            self.y,
+            self.rx,
+            self.ry,
+            0.0,
+            color,
+            is_antialias,
+        );
+    }
+
+    fn svg(&self, color: &ColorU8, precision: usize) -> String {
+        format!(
+            r#"<rect x="{}" y="{}" width="{}" height="{}" {}/>"#,
+            fmt_num(self.x - self.rx, precision),
+            fmt_num(self.y - self.ry, precision),
+            fmt_num(2.0 * self.rx, precision),
+            fmt_num(2.0 * self.ry, precision),
+            paint_attributes("fill", color)
+        )
+    }
+
+    fn bounding_box(&self) -> BoundingBox {
+        BoundingBox::from_center(self.x, self.y, self.rx, self.ry)
+    }
+}
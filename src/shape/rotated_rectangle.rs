@@ -0,0 +1,94 @@
+use tiny_skia::*;
+
+use super::{mutate_center_radii, rand_radii, rnd, BoundingBox, Brush, Shape};
+use crate::renderer;
+use crate::svg::{fmt_num, paint_attributes};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RotatedRectangle {
+    pub x: f32,
+    pub y: f32,
+    pub rx: f32,
+    pub ry: f32,
+    pub angle: f32, // degree
+}
+
+impl Shape for RotatedRectangle {
+    fn rand(brush: &Brush, x: f32, y: f32, rng: &mut rand::rngs::StdRng) -> RotatedRectangle {
+        let (rx, ry) = rand_radii(brush, rng);
+        let angle = rnd(rng, 0.0, 180.0);
+        RotatedRectangle {
+            x,
+            y,
+            rx,
+            ry,
+            angle,
+        }
+    }
+
+    fn mutate(
+        &self,
+        brush: &Brush,
+        rng: &mut rand::rngs::StdRng,
+    ) -> (RotatedRectangle, RotatedRectangle) {
+        let mut params1 = [self.x, self.y, self.rx, self.ry, self.angle];
+        //inverse shape
+        let mut params2 = params1;
+        mutate_center_radii(&mut params1, &mut params2, brush, rng);
+        let [x, y, rx, ry, angle] = params1;
+        let s1 = RotatedRectangle {
+            x,
+            y,
+            rx,
+            ry,
+            angle,
+        };
+        let [x, y, rx, ry, angle] = params2;
+        let s2 = RotatedRectangle {
+            x,
+            y,
+            rx,
+            ry,
+            angle,
+        };
+        (s1, s2)
+    }
+
+    fn draw(&self, pixmap: &mut Pixmap, color: &ColorU8, is_antialias: bool) {
+        renderer::fill_rect(
+            pixmap,
+            self.x,
+            self.y,
+            self.rx,
+            self.ry,
+            self.angle,
+            color,
+            is_antialias,
+        );
+    }
+
+    // rotated about its center, so the rotation stays even at angle 0 to
+    // tell it apart from a plain rectangle
+    fn svg(&self, color: &ColorU8, precision: usize) -> String {
+        format!(
+            r#"<rect x="{}" y="{}" width="{}" height="{}" {} transform="rotate({} {} {})"/>"#,
+            fmt_num(self.x - self.rx, precision),
+            fmt_num(self.y - self.ry, precision),
+            fmt_num(2.0 * self.rx, precision),
+            fmt_num(2.0 * self.ry, precision),
+            paint_attributes("fill", color),
+            fmt_num(self.angle, precision),
+            fmt_num(self.x, precision),
+            fmt_num(self.y, precision)
+        )
+    }
+
+    fn bounding_box(&self) -> BoundingBox {
+        let (sin, cos) = self.angle.to_radians().sin_cos();
+        let rx = self.rx;
+        let ry = self.ry;
+        let ex = rx * cos.abs() + ry * sin.abs();
+        let ey = rx * sin.abs() + ry * cos.abs();
+        BoundingBox::from_center(self.x, self.y, ex, ey)
+    }
+}
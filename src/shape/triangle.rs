@@ -0,0 +1,59 @@
+use tiny_skia::*;
+
+use super::{mutate_point, rand_point, rnd, BoundingBox, Brush, Shape};
+use crate::renderer;
+use crate::svg::{fmt_num, paint_attributes};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Triangle {
+    pub points: [f32; 6], // x1, y1, x2, y2, x3, y3
+}
+
+impl Triangle {
+    fn points(&self) -> [(f32, f32); 3] {
+        let p = &self.points;
+        [(p[0], p[1]), (p[2], p[3]), (p[4], p[5])]
+    }
+}
+
+impl Shape for Triangle {
+    fn rand(brush: &Brush, x: f32, y: f32, rng: &mut rand::rngs::StdRng) -> Triangle {
+        let (x2, y2) = rand_point(brush, x, y, rng);
+        let (x3, y3) = rand_point(brush, x, y, rng);
+        Triangle {
+            points: [x, y, x2, y2, x3, y3],
+        }
+    }
+
+    fn mutate(&self, brush: &Brush, rng: &mut rand::rngs::StdRng) -> (Triangle, Triangle) {
+        let mut s1 = *self;
+        //inverse shape
+        let mut s2 = *self;
+
+        let index = rnd(rng, 0, self.points.len() - 1);
+        mutate_point(&mut s1.points, &mut s2.points, index, brush, rng);
+        (s1, s2)
+    }
+
+    fn draw(&self, pixmap: &mut Pixmap, color: &ColorU8, is_antialias: bool) {
+        renderer::fill_polygon(pixmap, &self.points(), color, is_antialias);
+    }
+
+    fn svg(&self, color: &ColorU8, precision: usize) -> String {
+        let p: Vec<String> = self.points.iter().map(|&v| fmt_num(v, precision)).collect();
+        format!(
+            r#"<polygon points="{},{} {},{} {},{}" {}/>"#,
+            p[0],
+            p[1],
+            p[2],
+            p[3],
+            p[4],
+            p[5],
+            paint_attributes("fill", color)
+        )
+    }
+
+    fn bounding_box(&self) -> BoundingBox {
+        BoundingBox::from_points(&self.points())
+    }
+}
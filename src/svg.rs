@@ -0,0 +1,720 @@
+use std::fs;
+use std::io::Write;
+
+use tiny_skia::*;
+
+use crate::shape::{
+    Circle, CubicBezier, Ellipse, Primitive, QuadraticBezier, Rectangle, RotatedRectangle, Triangle,
+};
+use crate::DrawCommand;
+
+// fixed number of decimals without trailing zeros
+pub fn fmt_num(value: f32, precision: usize) -> String {
+    let s = format!("{:.*}", precision, value);
+    let s = if s.contains('.') {
+        s.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        &s
+    };
+    if s == "-0" {
+        "0".to_string()
+    } else {
+        s.to_string()
+    }
+}
+
+fn get_color_string(color: &ColorU8) -> String {
+    format!(
+        "#{:02x}{:02x}{:02x}",
+        color.red(),
+        color.green(),
+        color.blue()
+    )
+}
+
+// `fill="#rrggbb" fill-opacity=".."` for kind "fill", likewise for "stroke"
+pub fn paint_attributes(kind: &str, color: &ColorU8) -> String {
+    let mut s = format!(r#"{}="{}""#, kind, get_color_string(color));
+    if !color.is_opaque() {
+        let opacity = fmt_num(color.alpha() as f32 / 255.0, 3);
+        s.push_str(&format!(r#" {}-opacity="{}""#, kind, opacity));
+    }
+    s
+}
+
+// shortens the numbers and colors written by `svg_string`: "0.5" becomes
+// ".5" and "#aabbcc" becomes "#abc"
+fn minify(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let is_hex = |i: usize| bytes.get(i).is_some_and(|c| c.is_ascii_hexdigit());
+    let mut out = String::with_capacity(s.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let c = bytes[i];
+        let number_start = i == 0 || !(bytes[i - 1].is_ascii_digit() || bytes[i - 1] == b'.');
+        if c == b'0' && number_start && bytes.get(i + 1) == Some(&b'.') {
+            i += 1;
+            continue;
+        }
+        if c == b'#' && (1..7).all(|k| is_hex(i + k)) && bytes.get(i + 7) == Some(&b'"') {
+            let hex = &s[i + 1..i + 7];
+            let h = hex.as_bytes();
+            if h[0] == h[1] && h[2] == h[3] && h[4] == h[5] {
+                out.push('#');
+                out.push(h[0] as char);
+                out.push(h[2] as char);
+                out.push(h[4] as char);
+                i += 7;
+                continue;
+            }
+        }
+        out.push(c as char);
+        i += 1;
+    }
+    out
+}
+
+fn is_stroke(command: &DrawCommand) -> bool {
+    matches!(command.shape, Primitive::Quadratic(_) | Primitive::Cubic(_))
+}
+
+// alpha of all commands when it is the same and not opaque, written once as
+// the opacity of a parent group
+pub fn shared_alpha(commands: &[DrawCommand]) -> Option<u8> {
+    let alpha = commands.first()?.color.alpha();
+    if alpha == 255 || commands.iter().any(|cmd| cmd.color.alpha() != alpha) {
+        return None;
+    }
+    Some(alpha)
+}
+
+// `command` as written by `svg_string` when the commands share `alpha`
+fn command_string(
+    command: &DrawCommand,
+    alpha: Option<u8>,
+    precision: usize,
+    minify_output: bool,
+) -> String {
+    let c = command.color;
+    let color = match alpha {
+        Some(_) => ColorU8::from_rgba(c.red(), c.green(), c.blue(), 255),
+        None => c,
+    };
+    let s = command.shape.svg(&color, precision);
+    if minify_output {
+        minify(&s)
+    } else {
+        s + "\n"
+    }
+}
+
+pub fn svg_string(
+    w: u32,
+    h: u32,
+    commands: &[DrawCommand],
+    bg_color: &ColorU8,
+    precision: usize,
+    minify_output: bool,
+) -> String {
+    let newline = if minify_output { "" } else { "\n" };
+    let version = if minify_output {
+        ""
+    } else {
+        r#" version="1.1""#
+    };
+    let mut header = format!(
+        r#"<svg{} width="{}" height="{}" xmlns="http://www.w3.org/2000/svg">{}"#,
+        version, w, h, newline
+    );
+    header.push_str(&format!(
+        r#"<rect width="100%" height="100%" fill="{}"/>{}"#,
+        get_color_string(bg_color),
+        newline
+    ));
+
+    let alpha = shared_alpha(commands);
+    if let Some(alpha) = alpha {
+        let opacity = fmt_num(alpha as f32 / 255.0, 3);
+        header.push_str("<g");
+        if commands.iter().any(|cmd| !is_stroke(cmd)) {
+            header.push_str(&format!(r#" fill-opacity="{}""#, opacity));
+        }
+        if commands.iter().any(is_stroke) {
+            header.push_str(&format!(r#" stroke-opacity="{}""#, opacity));
+        }
+        header.push('>');
+        header.push_str(newline);
+    }
+
+    let mut data = if minify_output {
+        minify(&header)
+    } else {
+        header
+    };
+    for command in commands {
+        data.push_str(&command_string(command, alpha, precision, minify_output));
+    }
+
+    if alpha.is_some() {
+        data.push_str("</g>");
+        data.push_str(newline);
+    }
+    data.push_str("</svg>");
+    data.push_str(newline);
+
+    data
+}
+
+// bytes `svg_string` spends on `command` when the commands share `alpha`,
+// see `shared_alpha`
+pub fn command_size(
+    command: &DrawCommand,
+    alpha: Option<u8>,
+    precision: usize,
+    minify_output: bool,
+) -> usize {
+    command_string(command, alpha, precision, minify_output).len()
+}
+
+pub fn save_svg(
+    w: u32,
+    h: u32,
+    svg_name: &str,
+    commands: &[DrawCommand],
+    bg_color: &ColorU8,
+    precision: usize,
+    minify_output: bool,
+) {
+    let data = svg_string(w, h, commands, bg_color, precision, minify_output);
+    let mut f = fs::File::create(svg_name).unwrap();
+    f.write_all(data.as_bytes()).unwrap();
+}
+
+// what `svg_string` wrote
+#[derive(Clone, Debug, PartialEq)]
+pub struct SvgImage {
+    pub w: u32,
+    pub h: u32,
+    pub bg_color: ColorU8,
+    pub commands: Vec<DrawCommand>,
+}
+
+type Attributes<'a> = Vec<(&'a str, &'a str)>;
+
+// `key="value"` pairs of a tag without its name
+fn parse_attributes(s: &str) -> Result<Attributes<'_>, String> {
+    let mut attributes = Vec::new();
+    let mut rest = s;
+    while let Some(eq) = rest.find('=') {
+        let key = rest[..eq].trim();
+        let value = rest[eq + 1..].trim_start();
+        let quote = match value.chars().next() {
+            Some(c) if c == '"' || c == '\'' => c,
+            _ => return Err(format!("invalid attribute: {}", key)),
+        };
+        let value = &value[1..];
+        let end = value
+            .find(quote)
+            .ok_or(format!("invalid attribute: {}", key))?;
+        attributes.push((key, &value[..end]));
+        rest = &value[end + 1..];
+    }
+    Ok(attributes)
+}
+
+fn attribute<'a>(attributes: &[(&str, &'a str)], key: &str) -> Option<&'a str> {
+    attributes.iter().find(|(k, _)| *k == key).map(|(_, v)| *v)
+}
+
+// a finite number, "NaN" and "inf" parse as f32 too
+fn parse_finite(value: &str) -> Option<f32> {
+    value.parse().ok().filter(|v: &f32| v.is_finite())
+}
+
+// numbers separated by spaces or commas
+fn parse_numbers(s: &str) -> Result<Vec<f32>, String> {
+    s.split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|v| !v.is_empty())
+        .map(|v| parse_finite(v).ok_or(format!("invalid number: {}", v)))
+        .collect()
+}
+
+fn number(attributes: &[(&str, &str)], key: &str) -> Result<f32, String> {
+    let value = attribute(attributes, key).ok_or(format!("missing {}", key))?;
+    parse_finite(value).ok_or(format!("invalid {}: {}", key, value))
+}
+
+// a width, radius or the like, which the renderer cannot take negative
+fn size(attributes: &[(&str, &str)], key: &str) -> Result<f32, String> {
+    let value = number(attributes, key)?;
+    if value < 0.0 {
+        return Err(format!("negative {}: {}", key, value));
+    }
+    Ok(value)
+}
+
+// arguments of `name(..)` in a transform attribute
+fn transform_args(attributes: &[(&str, &str)], name: &str) -> Result<Vec<f32>, String> {
+    let value = match attribute(attributes, "transform") {
+        Some(value) => value.trim(),
+        None => return Ok(Vec::new()),
+    };
+    let args = value
+        .strip_prefix(name)
+        .and_then(|v| v.strip_prefix('('))
+        .and_then(|v| v.strip_suffix(')'))
+        .ok_or(format!("unsupported transform: {}", value))?;
+    parse_numbers(args)
+}
+
+fn rotation(attributes: &[(&str, &str)]) -> Result<f32, String> {
+    let args = transform_args(attributes, "rotate")?;
+    Ok(args.first().copied().unwrap_or(0.0))
+}
+
+// inverse of `paint_attributes`
+fn parse_paint(attributes: &[(&str, &str)], kind: &str) -> Result<ColorU8, String> {
+    let value = attribute(attributes, kind).ok_or(format!("missing {}", kind))?;
+    let hex = value
+        .strip_prefix('#')
+        .filter(|hex| [3, 6, 8].contains(&hex.len()))
+        .ok_or(format!("unsupported color: {}", value))?;
+    // "#abc" is short for "#aabbcc", "#rrggbbaa" is how the first versions
+    // wrote translucent colors
+    let digits = if hex.len() == 3 { 1 } else { 2 };
+    let channel = |i: usize| {
+        let v = u8::from_str_radix(&hex[digits * i..digits * (i + 1)], 16)
+            .map_err(|_| format!("unsupported color: {}", value))?;
+        Ok::<u8, String>(if digits == 1 { v * 17 } else { v })
+    };
+    let hex_alpha = if hex.len() == 8 { channel(3)? } else { 255 };
+    let opacity_key = format!("{}-opacity", kind);
+    let opacity = match attribute(attributes, &opacity_key) {
+        Some(_) => number(attributes, &opacity_key)?,
+        None => 1.0,
+    };
+    let alpha = (hex_alpha as f32 * opacity).round().clamp(0.0, 255.0) as u8;
+    Ok(ColorU8::from_rgba(
+        channel(0)?,
+        channel(1)?,
+        channel(2)?,
+        alpha,
+    ))
+}
+
+fn parse_path(attributes: &[(&str, &str)]) -> Result<DrawCommand, String> {
+    let d = attribute(attributes, "d").ok_or("missing d")?;
+    let p = parse_numbers(&d.replace(&['M', 'Q', 'C'][..], " "))?;
+    let width = size(attributes, "stroke-width")?;
+    let shape = if d.contains('Q') && p.len() == 6 {
+        let mut points = [0.0; 6];
+        points.copy_from_slice(&p);
+        Primitive::Quadratic(QuadraticBezier { points, width })
+    } else if d.contains('C') && p.len() == 8 {
+        let mut points = [0.0; 8];
+        points.copy_from_slice(&p);
+        Primitive::Cubic(CubicBezier { points, width })
+    } else {
+        return Err(format!("unsupported path: {}", d));
+    };
+    let color = parse_paint(attributes, "stroke")?;
+    Ok(DrawCommand { shape, color })
+}
+
+// `offset` is the translation of the enclosing groups
+fn parse_shape(
+    name: &str,
+    attributes: &[(&str, &str)],
+    offset: (f32, f32),
+) -> Result<DrawCommand, String> {
+    let (tx, ty) = offset;
+    let shape = match name {
+        "ellipse" => {
+            let cx = attribute(attributes, "cx").map_or(Ok(0.0), |_| number(attributes, "cx"))?;
+            let cy = attribute(attributes, "cy").map_or(Ok(0.0), |_| number(attributes, "cy"))?;
+            Primitive::Ellipse(Ellipse {
+                x: tx + cx,
+                y: ty + cy,
+                rx: size(attributes, "rx")?,
+                ry: size(attributes, "ry")?,
+                angle: rotation(attributes)?,
+            })
+        }
+        "circle" => Primitive::Circle(Circle {
+            x: tx + number(attributes, "cx")?,
+            y: ty + number(attributes, "cy")?,
+            r: size(attributes, "r")?,
+        }),
+        "rect" => {
+            let x = number(attributes, "x")?;
+            let y = number(attributes, "y")?;
+            let rx = 0.5 * size(attributes, "width")?;
+            let ry = 0.5 * size(attributes, "height")?;
+            if attribute(attributes, "transform").is_some() {
+                // `rotate(a cx cy)` names the center exactly
+                let args = transform_args(attributes, "rotate")?;
+                let (cx, cy) = match args[..] {
+                    [_, cx, cy] => (cx, cy),
+                    _ => (x + rx, y + ry),
+                };
+                Primitive::RotatedRectangle(RotatedRectangle {
+                    x: tx + cx,
+                    y: ty + cy,
+                    rx,
+                    ry,
+                    angle: rotation(attributes)?,
+                })
+            } else {
+                Primitive::Rectangle(Rectangle {
+                    x: tx + x + rx,
+                    y: ty + y + ry,
+                    rx,
+                    ry,
+                })
+            }
+        }
+        "polygon" => {
+            let p = parse_numbers(attribute(attributes, "points").ok_or("missing points")?)?;
+            if p.len() != 6 {
+                return Err("only triangles are supported".to_string());
+            }
+            let mut points = [0.0; 6];
+            for i in 0..3 {
+                points[2 * i] = tx + p[2 * i];
+                points[2 * i + 1] = ty + p[2 * i + 1];
+            }
+            Primitive::Triangle(Triangle { points })
+        }
+        "path" => return parse_path(attributes),
+        _ => return Err(format!("unsupported element: {}", name)),
+    };
+    let color = parse_paint(attributes, "fill")?;
+    Ok(DrawCommand { shape, color })
+}
+
+// a side of the image, at least one pixel
+fn svg_size(attributes: &[(&str, &str)], key: &str) -> Result<u32, String> {
+    let value = size(attributes, key)?;
+    if value < 1.0 {
+        return Err(format!("invalid {}: {}", key, value));
+    }
+    Ok(value as u32)
+}
+
+// reads back the elements written by `svg_string`
+pub fn parse_svg(data: &str) -> Result<SvgImage, String> {
+    let mut size = None;
+    let mut bg_color = None;
+    let mut commands = Vec::new();
+    // translation and inherited opacity of the open groups
+    let mut groups: Vec<((f32, f32), Attributes)> = Vec::new();
+
+    let mut rest = data;
+    while let Some(start) = rest.find('<') {
+        let end = rest[start..].find('>').ok_or("unterminated tag")?;
+        let tag = &rest[start + 1..start + end];
+        rest = &rest[start + end + 1..];
+
+        if tag.starts_with('?') || tag.starts_with('!') {
+            continue;
+        }
+        if let Some(name) = tag.strip_prefix('/') {
+            if name.trim() == "g" {
+                groups.pop();
+            }
+            continue;
+        }
+        let self_closing = tag.ends_with('/');
+        let tag = tag.trim_end_matches('/');
+        let name_end = tag.find(char::is_whitespace).unwrap_or(tag.len());
+        let name = &tag[..name_end];
+        let mut attributes = parse_attributes(&tag[name_end..])?;
+
+        let offset = groups
+            .iter()
+            .fold((0.0, 0.0), |(x, y), ((dx, dy), _)| (x + dx, y + dy));
+        for (_, inherited) in groups.iter().rev() {
+            for &(key, value) in inherited.iter() {
+                if attribute(&attributes, key).is_none() {
+                    attributes.push((key, value));
+                }
+            }
+        }
+        match name {
+            "svg" => {
+                let w = svg_size(&attributes, "width")?;
+                let h = svg_size(&attributes, "height")?;
+                size = Some((w, h));
+            }
+            "g" => {
+                if !self_closing {
+                    let args = transform_args(&attributes, "translate")?;
+                    let dx = args.first().copied().unwrap_or(0.0);
+                    let dy = args.get(1).copied().unwrap_or(0.0);
+                    let inherited = attributes
+                        .iter()
+                        .filter(|(key, _)| key.ends_with("-opacity"))
+                        .copied()
+                        .collect();
+                    groups.push(((dx, dy), inherited));
+                }
+            }
+            "rect" if attribute(&attributes, "width") == Some("100%") => {
+                bg_color = Some(parse_paint(&attributes, "fill")?);
+            }
+            _ => commands.push(parse_shape(name, &attributes, offset)?),
+        }
+    }
+
+    let (w, h) = size.ok_or("missing svg element")?;
+    let bg_color = bg_color.ok_or("missing background rect")?;
+    Ok(SvgImage {
+        w,
+        h,
+        bg_color,
+        commands,
+    })
+}
+
+pub fn load_svg(svg_name: &str) -> Result<SvgImage, String> {
+    let data = fs::read_to_string(svg_name).map_err(|e| format!("{}: {}", svg_name, e))?;
+    parse_svg(&data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn fmt_num_test() {
+        assert_eq!(fmt_num(1.25, 3), "1.25");
+        assert_eq!(fmt_num(1.25, 0), "1");
+        assert_eq!(fmt_num(10.0, 2), "10");
+        assert_eq!(fmt_num(-0.001, 2), "0");
+        assert_eq!(fmt_num(-1.256, 2), "-1.26");
+    }
+
+    #[test]
+    fn svg_string_test() {
+        let color = ColorU8::from_rgba(255, 0, 0, 128);
+        let commands = [
+            DrawCommand {
+                shape: Primitive::Ellipse(Ellipse {
+                    x: 4.0,
+                    y: 4.5,
+                    rx: 2.25,
+                    ry: 3.0,
+                    angle: 10.0,
+                }),
+                color,
+            },
+            DrawCommand {
+                shape: Primitive::Triangle(Triangle {
+                    points: [0.0, 0.0, 7.0, 0.0, 0.0, 7.0],
+                }),
+                color,
+            },
+        ];
+        let bg_color = ColorU8::from_rgba(0, 0, 0, 255);
+        let data = svg_string(8, 8, &commands, &bg_color, 1, false);
+
+        let lines: Vec<&str> = data.lines().collect();
+        assert_eq!(lines.len(), 7);
+        assert!(lines[1].contains(r##"fill="#000000""##));
+        assert_eq!(lines[2], r#"<g fill-opacity="0.502">"#);
+        assert!(lines[3].starts_with(r#"<ellipse cx="4" cy="4.5" rx="2.2" ry="3""#));
+        assert!(lines[3].ends_with(r#"transform="rotate(10 4 4.5)"/>"#));
+        assert_eq!(
+            lines[4],
+            r##"<polygon points="0,0 7,0 0,7" fill="#ff0000"/>"##
+        );
+        assert_eq!(lines[5], "</g>");
+
+        let data = svg_string(8, 8, &commands, &bg_color, 1, true);
+        assert!(!data.contains('\n'));
+        assert!(data.contains(r#"<g fill-opacity=".502">"#));
+        assert!(data.contains(r##"fill="#f00""##));
+    }
+
+    #[test]
+    fn parse_svg_test() {
+        let shapes = [
+            Primitive::Ellipse(Ellipse {
+                x: 4.0,
+                y: 4.5,
+                rx: 2.25,
+                ry: 3.0,
+                angle: 10.5,
+            }),
+            Primitive::Circle(Circle {
+                x: 1.0,
+                y: 2.0,
+                r: 3.5,
+            }),
+            Primitive::Triangle(Triangle {
+                points: [0.0, 0.0, 7.0, 0.0, 0.0, 7.25],
+            }),
+            Primitive::Rectangle(Rectangle {
+                x: 3.0,
+                y: 4.0,
+                rx: 1.5,
+                ry: 2.0,
+            }),
+            Primitive::RotatedRectangle(RotatedRectangle {
+                x: 5.0,
+                y: 3.0,
+                rx: 2.0,
+                ry: 0.5,
+                angle: 135.0,
+            }),
+            Primitive::Quadratic(QuadraticBezier {
+                points: [0.0, 1.0, 2.0, 3.0, 4.0, 5.0],
+                width: 0.75,
+            }),
+            Primitive::Cubic(CubicBezier {
+                points: [0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0],
+                width: 1.5,
+            }),
+        ];
+        let commands: Vec<DrawCommand> = shapes
+            .iter()
+            .enumerate()
+            .map(|(i, &shape)| DrawCommand {
+                shape,
+                color: ColorU8::from_rgba(30 * i as u8, 255, 7, 200 + 9 * i as u8),
+            })
+            .collect();
+        let bg_color = ColorU8::from_rgba(1, 2, 3, 255);
+        let data = svg_string(8, 9, &commands, &bg_color, 2, false);
+
+        for &minify_output in [false, true].iter() {
+            let data = svg_string(8, 9, &commands, &bg_color, 2, minify_output);
+            let empty = svg_string(8, 9, &[], &bg_color, 2, minify_output);
+            let size: usize = commands
+                .iter()
+                .map(|cmd| command_size(cmd, None, 2, minify_output))
+                .sum();
+            assert_eq!(data.len(), empty.len() + size);
+
+            let image = parse_svg(&data).unwrap();
+            assert_eq!(
+                image,
+                SvgImage {
+                    w: 8,
+                    h: 9,
+                    bg_color,
+                    commands: commands.clone(),
+                }
+            );
+        }
+
+        // the output of the first versions still reads
+        let old = r##"<svg version="1.1" width="200" height="150" xmlns="http://www.w3.org/2000/svg">
+<rect width="100%" height="100%" fill="#010203" />
+<g transform="translate(120,80)"><ellipse rx="30" ry="12" fill="#a0b0c080" transform="rotate(-30)"/></g>
+<g transform="translate(7,9)"><ellipse rx="4" ry="1" fill="#ff0010" transform="rotate(89)"/></g>
+</svg>
+"##;
+        let image = parse_svg(old).unwrap();
+        assert_eq!((image.w, image.h), (200, 150));
+        assert_eq!(image.bg_color, ColorU8::from_rgba(1, 2, 3, 255));
+        let expected = vec![
+            DrawCommand {
+                shape: Primitive::Ellipse(Ellipse {
+                    x: 120.0,
+                    y: 80.0,
+                    rx: 30.0,
+                    ry: 12.0,
+                    angle: -30.0,
+                }),
+                color: ColorU8::from_rgba(0xa0, 0xb0, 0xc0, 0x80),
+            },
+            DrawCommand {
+                shape: Primitive::Ellipse(Ellipse {
+                    x: 7.0,
+                    y: 9.0,
+                    rx: 4.0,
+                    ry: 1.0,
+                    angle: 89.0,
+                }),
+                color: ColorU8::from_rgba(255, 0, 16, 255),
+            },
+        ];
+        assert_eq!(image.commands, expected);
+        assert!(parse_svg(&old.replace("#a0b0c080", "#a0b0c08")).is_err());
+        // rejected here rather than when the shapes are drawn
+        assert!(parse_svg(&old.replace(r#"rx="30""#, r#"rx="-30""#)).is_err());
+        assert!(parse_svg(&old.replace(r#"ry="1""#, r#"ry="NaN""#)).is_err());
+        assert!(parse_svg(&old.replace("rotate(89)", "rotate(inf)")).is_err());
+        assert!(parse_svg(&old.replace(r#"width="200""#, r#"width="0""#)).is_err());
+        let rect = r##"<rect x="1" y="1" width="-4" height="2" fill="#ffffff"/>"##;
+        assert!(parse_svg(&old.replace("</svg>", &format!("{}\n</svg>", rect))).is_err());
+
+        assert!(parse_svg(&data.replace("polygon", "polyline")).is_err());
+    }
+
+    fn render(image: &SvgImage) -> Pixmap {
+        let mut pixmap = Pixmap::new(image.w, image.h).unwrap();
+        let c = image.bg_color;
+        pixmap.fill(Color::from_rgba8(c.red(), c.green(), c.blue(), c.alpha()));
+        for cmd in image.commands.iter() {
+            cmd.shape.draw(&mut pixmap, &cmd.color, true);
+        }
+        pixmap
+    }
+
+    #[test]
+    fn minify_render_test() {
+        let shapes = [
+            Primitive::Ellipse(Ellipse {
+                x: 14.25,
+                y: 10.5,
+                rx: 6.0,
+                ry: 0.5,
+                angle: 30.0,
+            }),
+            Primitive::Ellipse(Ellipse {
+                x: 0.5,
+                y: 3.0,
+                rx: 4.75,
+                ry: 2.0,
+                angle: 0.0,
+            }),
+            Primitive::RotatedRectangle(RotatedRectangle {
+                x: 9.0,
+                y: 20.0,
+                rx: 5.5,
+                ry: 0.25,
+                angle: 0.0,
+            }),
+            Primitive::Quadratic(QuadraticBezier {
+                points: [0.5, 31.0, 16.0, 0.25, 31.5, 30.0],
+                width: 0.5,
+            }),
+        ];
+        let commands: Vec<DrawCommand> = shapes
+            .iter()
+            .enumerate()
+            .map(|(i, &shape)| DrawCommand {
+                shape,
+                color: ColorU8::from_rgba(0x11 * i as u8, 0x80, 0xff, 96),
+            })
+            .collect();
+        let image = SvgImage {
+            w: 32,
+            h: 32,
+            bg_color: ColorU8::from_rgba(0xee, 0xee, 0xee, 255),
+            commands,
+        };
+        let expected = render(&image);
+
+        let plain = svg_string(32, 32, &image.commands, &image.bg_color, 2, false);
+        let data = svg_string(32, 32, &image.commands, &image.bg_color, 2, true);
+        assert!(data.len() < plain.len());
+        assert!(data.contains(r#"<g fill-opacity=".376" stroke-opacity=".376">"#));
+        assert!(data.contains(r##"<ellipse cx=".5" cy="3" rx="4.75" ry="2" fill="#1180ff"/>"##));
+
+        let parsed = parse_svg(&data).unwrap();
+        assert_eq!(parsed, image);
+        assert_eq!(render(&parsed).data(), expected.data());
+    }
+}